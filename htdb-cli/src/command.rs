@@ -1,11 +1,15 @@
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::bytes::complete::take_till;
+use nom::character::complete::i64;
 use nom::character::complete::space0;
 use nom::character::complete::space1;
+use nom::character::complete::u64;
 use nom::combinator::map;
+use nom::combinator::opt;
 use nom::error::convert_error;
 use nom::error::ParseError;
+use nom::sequence::preceded;
 use nom::sequence::tuple;
 use nom::Err as NomErr;
 use nom::Parser;
@@ -20,6 +24,28 @@ pub enum Command<'a> {
         hash_key: &'a str,
         tree_key: &'a str,
         data: &'a str,
+        ttl: Option<u64>,
+    },
+    PutNx {
+        hash_key: &'a str,
+        tree_key: &'a str,
+        data: &'a str,
+    },
+    Cas {
+        hash_key: &'a str,
+        tree_key: &'a str,
+        expected: Option<&'a str>,
+        data: Option<&'a str>,
+    },
+    IncrBy {
+        hash_key: &'a str,
+        tree_key: &'a str,
+        amount: i64,
+    },
+    Append {
+        hash_key: &'a str,
+        tree_key: &'a str,
+        data: &'a str,
     },
     Contains {
         hash_key: &'a str,
@@ -33,6 +59,13 @@ pub enum Command<'a> {
         hash_key: &'a str,
         tree_start: &'a str,
         tree_end: &'a str,
+        limit: Option<u64>,
+        after: Option<&'a str>,
+    },
+    DelRange {
+        hash_key: &'a str,
+        tree_start: &'a str,
+        tree_end: &'a str,
     },
     Succ {
         hash_key: &'a str,
@@ -42,13 +75,24 @@ pub enum Command<'a> {
         hash_key: &'a str,
         tree_key: &'a str,
     },
+    Partitions {},
+    Drop {
+        hash_key: &'a str,
+    },
+    Rename {
+        hash_key: &'a str,
+        new_hash_key: &'a str,
+    },
     Count {},
+    Stats {},
     Show {},
     Save {},
     Load {},
     Exit {},
 }
 
+const NIL: &str = "NIL";
+
 fn is_whitespace(ch: char) -> bool {
     ch.is_whitespace()
 }
@@ -84,8 +128,113 @@ where
             space1,
             take_till(is_whitespace),
             space0,
+            opt(preceded(tuple((tag("EX"), space1)), u64)),
+            space0,
+        )),
+        |(_, _, hash_key, _, tree_key, _, data, _, ttl, _)| Command::Put {
+            hash_key,
+            tree_key,
+            data,
+            ttl,
+        },
+    )
+}
+
+fn parse_putnx<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(
+        tuple((
+            tag("PUTNX"),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space0,
+        )),
+        |(_, _, hash_key, _, tree_key, _, data, _)| Command::PutNx {
+            hash_key,
+            tree_key,
+            data,
+        },
+    )
+}
+
+/// Parses CAS command, `NIL` instead of value means missing value.
+fn parse_cas<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    let value = || {
+        map(take_till(is_whitespace), |value| {
+            Some(value).filter(|&value| value != NIL)
+        })
+    };
+
+    map(
+        tuple((
+            tag("CAS"),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            value(),
+            space1,
+            value(),
+            space0,
         )),
-        |(_, _, hash_key, _, tree_key, _, data, _)| Command::Put {
+        |(_, _, hash_key, _, tree_key, _, expected, _, data, _)| Command::Cas {
+            hash_key,
+            tree_key,
+            expected,
+            data,
+        },
+    )
+}
+
+fn parse_incrby<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(
+        tuple((
+            tag("INCRBY"),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            i64,
+            space0,
+        )),
+        |(_, _, hash_key, _, tree_key, _, amount, _)| Command::IncrBy {
+            hash_key,
+            tree_key,
+            amount,
+        },
+    )
+}
+
+fn parse_append<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(
+        tuple((
+            tag("APPEND"),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space0,
+        )),
+        |(_, _, hash_key, _, tree_key, _, data, _)| Command::Append {
             hash_key,
             tree_key,
             data,
@@ -127,6 +276,7 @@ where
     )
 }
 
+/// Parses RANGE command with optional page size and key to continue after.
 fn parse_range<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
 where
     E: ParseError<&'a str>,
@@ -141,8 +291,40 @@ where
             space1,
             take_till(is_whitespace),
             space0,
+            opt(preceded(tuple((tag("LIMIT"), space1)), u64)),
+            space0,
+            opt(preceded(
+                tuple((tag("AFTER"), space1)),
+                take_till(is_whitespace),
+            )),
+            space0,
         )),
-        |(_, _, hash_key, _, tree_start, _, tree_end, _)| Command::Range {
+        |(_, _, hash_key, _, tree_start, _, tree_end, _, limit, _, after, _)| Command::Range {
+            hash_key,
+            tree_start,
+            tree_end,
+            limit,
+            after,
+        },
+    )
+}
+
+fn parse_delrange<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(
+        tuple((
+            tag("DELRANGE"),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space0,
+        )),
+        |(_, _, hash_key, _, tree_start, _, tree_end, _)| Command::DelRange {
             hash_key,
             tree_start,
             tree_end,
@@ -184,6 +366,45 @@ where
     )
 }
 
+fn parse_partitions<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(tuple((tag("PARTITIONS"), space0)), |(_, _)| {
+        Command::Partitions {}
+    })
+}
+
+fn parse_drop<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(
+        tuple((tag("DROP"), space1, take_till(is_whitespace), space0)),
+        |(_, _, hash_key, _)| Command::Drop { hash_key },
+    )
+}
+
+fn parse_rename<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(
+        tuple((
+            tag("RENAME"),
+            space1,
+            take_till(is_whitespace),
+            space1,
+            take_till(is_whitespace),
+            space0,
+        )),
+        |(_, _, hash_key, _, new_hash_key, _)| Command::Rename {
+            hash_key,
+            new_hash_key,
+        },
+    )
+}
+
 fn parse_count<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
 where
     E: ParseError<&'a str>,
@@ -191,6 +412,13 @@ where
     map(tuple((tag("COUNT"), space0)), |(_, _)| Command::Count {})
 }
 
+fn parse_stats<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
+where
+    E: ParseError<&'a str>,
+{
+    map(tuple((tag("STATS"), space0)), |(_, _)| Command::Stats {})
+}
+
 fn parse_show<'a, E>() -> impl Parser<&'a str, Command<'a>, E>
 where
     E: ParseError<&'a str>,
@@ -228,13 +456,22 @@ where
             space0,
             alt((
                 parse_get(),
+                parse_putnx(),
                 parse_put(),
+                parse_cas(),
+                parse_incrby(),
+                parse_append(),
                 parse_contains(),
                 parse_delete(),
+                parse_delrange(),
                 parse_range(),
                 parse_succ(),
                 parse_pred(),
+                parse_partitions(),
+                parse_drop(),
+                parse_rename(),
                 parse_count(),
+                parse_stats(),
                 parse_show(),
                 parse_save(),
                 parse_load(),
@@ -247,7 +484,7 @@ where
 }
 
 impl<'a> Command<'a> {
-    pub fn parse(input: &str) -> Result<Command, String> {
+    pub fn parse(input: &str) -> Result<Command<'_>, String> {
         match parser().parse(input) {
             Ok((_tail, command)) => Ok(command),
             Err(NomErr::Error(error)) | Err(NomErr::Failure(error)) => {
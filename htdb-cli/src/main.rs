@@ -6,11 +6,16 @@ mod options;
 
 use crate::command::Command;
 use crate::options::Options;
+use htdb_sys::Concat;
 use htdb_sys::Config;
 use htdb_sys::Database;
+use htdb_sys::IntegerAdd;
+use htdb_sys::LoadStatus;
 use htdb_sys::PrintVisiter;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::ops::Bound;
+use std::time::Duration;
 use structopt::StructOpt;
 
 fn main() {
@@ -22,8 +27,16 @@ fn main() {
     let config = Config::default()
         .set_max_page_size(options.page_size())
         .set_max_pages(options.memory_pages())
-        .set_storage_path(options.storage_path());
-    let mut database: Database<String, String, String> = Database::new(config);
+        .set_storage_path(options.storage_path())
+        .set_write_ahead_log(options.write_ahead_log());
+    let mut database: Database<String, String, String> = match Database::open(config) {
+        Ok(database) => database,
+        Err(error) => {
+            error!("Failed to open database: {}", error);
+
+            return;
+        }
+    };
     let mut editor = Editor::<()>::new();
 
     info!("Entered to REPL mode");
@@ -61,16 +74,83 @@ fn main() {
                     hash_key,
                     tree_key,
                     data,
+                    ttl,
                 }) => {
                     let hash_key = hash_key.into();
                     let tree_key = tree_key.into();
                     let data = data.into();
+                    let result = match ttl {
+                        Some(ttl) => database.put_with_ttl(
+                            hash_key,
+                            tree_key,
+                            data,
+                            Duration::from_secs(ttl),
+                        ),
+                        None => database.put(hash_key, tree_key, data),
+                    };
 
-                    match database.put(hash_key, tree_key, data) {
+                    match result {
                         Ok(_replaced) => println!("OK"),
                         Err(error) => println!("ERR {}", error),
                     }
                 }
+                Ok(Command::PutNx {
+                    hash_key,
+                    tree_key,
+                    data,
+                }) => match database.put_if_absent(hash_key.into(), tree_key.into(), data.into()) {
+                    Ok(true) => println!("OK TRUE"),
+                    Ok(false) => println!("OK FALSE"),
+                    Err(error) => println!("ERR {}", error),
+                },
+                Ok(Command::Cas {
+                    hash_key,
+                    tree_key,
+                    expected,
+                    data,
+                }) => {
+                    let expected = expected.map(String::from);
+                    let data = data.map(String::from);
+
+                    match database.compare_and_swap(
+                        hash_key.into(),
+                        tree_key.into(),
+                        expected.as_ref(),
+                        data,
+                    ) {
+                        Ok(true) => println!("OK TRUE"),
+                        Ok(false) => println!("OK FALSE"),
+                        Err(error) => println!("ERR {}", error),
+                    }
+                }
+                Ok(Command::IncrBy {
+                    hash_key,
+                    tree_key,
+                    amount,
+                }) => match database.merge_with(
+                    hash_key.into(),
+                    tree_key.into(),
+                    amount.to_string(),
+                    &IntegerAdd,
+                ) {
+                    Ok(Some(data)) => println!("OK {} {} {}", hash_key, tree_key, data),
+                    Ok(None) => println!("OK"),
+                    Err(error) => println!("ERR {}", error),
+                },
+                Ok(Command::Append {
+                    hash_key,
+                    tree_key,
+                    data,
+                }) => match database.merge_with(
+                    hash_key.into(),
+                    tree_key.into(),
+                    data.into(),
+                    &Concat,
+                ) {
+                    Ok(Some(data)) => println!("OK {} {} {}", hash_key, tree_key, data),
+                    Ok(None) => println!("OK"),
+                    Err(error) => println!("ERR {}", error),
+                },
                 Ok(Command::Contains { hash_key, tree_key }) => {
                     let hash_key = hash_key.into();
                     let tree_key = tree_key.into();
@@ -95,6 +175,8 @@ fn main() {
                     hash_key,
                     tree_start,
                     tree_end,
+                    limit: None,
+                    after: None,
                 }) => {
                     let hash_key = hash_key.into();
                     let tree_start = tree_start.into();
@@ -109,6 +191,48 @@ fn main() {
                         Err(error) => println!("ERR {}", error),
                     }
                 }
+                Ok(Command::Range {
+                    hash_key,
+                    tree_start,
+                    tree_end,
+                    limit,
+                    after,
+                }) => {
+                    let hash_key = hash_key.into();
+                    let range_start = match after {
+                        Some(after) if after >= tree_start => Bound::Excluded(after.to_string()),
+                        _ => Bound::Included(tree_start.to_string()),
+                    };
+                    let range_end = Bound::Included(tree_end.to_string());
+                    let limit = limit.map_or(usize::MAX, |limit| limit as usize);
+
+                    match database.range_page(&hash_key, (range_start, range_end), limit) {
+                        Ok((entries, cursor)) => {
+                            for (key, value) in entries {
+                                println!("{} {} {}", hash_key, key, value);
+                            }
+
+                            match cursor {
+                                Some(cursor) => println!("OK NEXT {}", cursor.last_key()),
+                                None => println!("OK"),
+                            }
+                        }
+                        Err(error) => println!("ERR {}", error),
+                    }
+                }
+                Ok(Command::DelRange {
+                    hash_key,
+                    tree_start,
+                    tree_end,
+                }) => {
+                    let tree_start = tree_start.to_string();
+                    let tree_end = tree_end.to_string();
+
+                    match database.delete_range(&hash_key.into(), tree_start..=tree_end) {
+                        Ok(count) => println!("OK {}", count),
+                        Err(error) => println!("ERR {}", error),
+                    }
+                }
                 Ok(Command::Succ { hash_key, tree_key }) => {
                     let hash_key = hash_key.into();
                     let tree_key = tree_key.into();
@@ -129,21 +253,70 @@ fn main() {
                         Err(error) => println!("ERR {}", error),
                     }
                 }
+                Ok(Command::Partitions {}) => {
+                    for hash_key in database.partitions() {
+                        println!("{} {}", hash_key, database.partition_len(hash_key));
+                    }
+
+                    println!("OK");
+                }
+                Ok(Command::Drop { hash_key }) => match database.drop_partition(&hash_key.into()) {
+                    Ok(true) => println!("OK TRUE"),
+                    Ok(false) => println!("OK FALSE"),
+                    Err(error) => println!("ERR {}", error),
+                },
+                Ok(Command::Rename {
+                    hash_key,
+                    new_hash_key,
+                }) => {
+                    let hash_key = hash_key.into();
+                    let new_hash_key = new_hash_key.into();
+
+                    match database.rename_partition(&hash_key, new_hash_key) {
+                        Ok(true) => println!("OK TRUE"),
+                        Ok(false) => println!("OK FALSE"),
+                        Err(error) => println!("ERR {}", error),
+                    }
+                }
                 Ok(Command::Count {}) => match database.count() {
                     Ok(count) => println!("OK {}", count),
                     Err(error) => println!("ERR {}", error),
                 },
-                Ok(Command::Show {}) => {
-                    database.visit(&mut PrintVisiter::default());
+                Ok(Command::Stats {}) => match database.stats() {
+                    Ok(stats) => {
+                        let duration = |duration: Option<Duration>| match duration {
+                            Some(duration) => format!("{:?}", duration),
+                            None => "NIL".to_string(),
+                        };
 
-                    println!("OK");
-                }
+                        println!("PARTITIONS {}", stats.partitions());
+                        println!("PAGES {}", stats.pages());
+                        println!("ENTRIES {}", stats.entries());
+                        println!("MIN_FILL {:.3}", stats.min_page_fill());
+                        println!("AVG_FILL {:.3}", stats.average_page_fill());
+                        println!("MAX_FILL {:.3}", stats.max_page_fill());
+                        println!("SPLITS {}", stats.splits());
+                        println!("REMOVED_PAGES {}", stats.removed_pages());
+                        println!("MEMORY {}", stats.memory());
+                        println!("LAST_SAVE {}", duration(stats.last_save()));
+                        println!("LAST_LOAD {}", duration(stats.last_load()));
+                        println!("OK");
+                    }
+                    Err(error) => println!("ERR {}", error),
+                },
+                Ok(Command::Show {}) => match database.visit(&mut PrintVisiter::default()) {
+                    Ok(()) => println!("OK"),
+                    Err(error) => println!("ERR {}", error),
+                },
                 Ok(Command::Save {}) => match database.save() {
                     Ok(()) => println!("OK"),
                     Err(error) => println!("ERR {}", error),
                 },
                 Ok(Command::Load {}) => match database.load() {
-                    Ok(()) => println!("OK"),
+                    Ok(LoadStatus::Loaded) => println!("OK"),
+                    Ok(LoadStatus::Repaged { saved_page_size }) => {
+                        println!("OK repaged from page size {}", saved_page_size)
+                    }
                     Err(error) => println!("ERR {}", error),
                 },
                 Ok(Command::Exit {}) => {
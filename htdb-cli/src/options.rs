@@ -12,6 +12,9 @@ pub struct Options {
 
     #[structopt(short, long, default_value = ".", parse(from_os_str))]
     storage_path: PathBuf,
+
+    #[structopt(short, long)]
+    write_ahead_log: bool,
 }
 
 impl Options {
@@ -26,4 +29,8 @@ impl Options {
     pub fn storage_path(&self) -> &Path {
         self.storage_path.as_path()
     }
+
+    pub fn write_ahead_log(&self) -> bool {
+        self.write_ahead_log
+    }
 }
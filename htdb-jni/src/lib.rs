@@ -1,9 +1,17 @@
 #[macro_use]
 mod util;
 
+use htdb_sys::BuiltinCodec;
+use htdb_sys::Codec;
+use htdb_sys::Concat;
 use htdb_sys::Config;
-use htdb_sys::Database;
+use htdb_sys::IntegerAdd;
+use htdb_sys::SharedDatabase;
+use htdb_sys::SharedTransaction;
+use jni::errors::Error as JniError;
+use jni::errors::Result as JniResult;
 use jni::objects::JClass;
+use jni::objects::JMethodID;
 use jni::objects::JObject;
 use jni::objects::JString;
 use jni::objects::JValue;
@@ -14,27 +22,62 @@ use jni::sys::jbyteArray;
 use jni::sys::jint;
 use jni::sys::jlong;
 use jni::sys::jobject;
+use jni::sys::jobjectArray;
+use jni::sys::jsize;
 use jni::JNIEnv;
+use std::collections::hash_map::RandomState;
+use std::ops::Bound;
 use std::ptr::null_mut;
+use std::time::Duration;
 
 const ILLEGAL_ARGUMENT: &str = "java/lang/IllegalArgumentException";
 const CLASS_ENTRY: &str = "ru/snake/htdb/entry/RawEntry";
+const CLASS_STATS: &str = "ru/snake/htdb/stats/RawStats";
+const CLASS_BYTE_ARRAY: &str = "[B";
 const METHOD_ENTRY_INIT: &str = "<init>";
+const METHOD_STATS_INIT: &str = "<init>";
 const METHOD_CALLBACL_ACCEPT: &str = "accept";
+const METHOD_CALLBACK_APPLY: &str = "apply";
 const SIGNATURE_ENTRY_INIT: &str = "([B[B)V";
+const SIGNATURE_STATS_INIT: &str = "(JJJDDDJJJJJ)V";
 const SIGNATURE_CALLBACL_ACCEPT: &str = "([B[B)Z";
+const SIGNATURE_CALLBACK_APPLY: &str = "([B)[B";
 
-type JavaDatabase = Database<Vec<u8>, Vec<u8>, Vec<u8>>;
+type JavaDatabase = SharedDatabase<Vec<u8>, Vec<u8>, Vec<u8>, RandomState, BuiltinCodec>;
+type JavaTransaction = SharedTransaction<Vec<u8>, Vec<u8>, Vec<u8>, RandomState, BuiltinCodec>;
 
 #[no_mangle]
 pub extern "system" fn Java_ru_snake_htdb_HTDBNative_create(
+    env: JNIEnv,
+    class: JClass,
+    page_size: jint,
+    n_pages: jint,
+    storage_path: JString,
+) -> jlong {
+    Java_ru_snake_htdb_HTDBNative_createWithCodec(
+        env,
+        class,
+        page_size,
+        n_pages,
+        storage_path,
+        BuiltinCodec::Bincode.id() as jint,
+    )
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_createWithCodec(
     env: JNIEnv,
     _class: JClass,
     page_size: jint,
     n_pages: jint,
     storage_path: JString,
+    codec: jint,
 ) -> jlong {
     let mut config = Config::default();
+    let codec = match BuiltinCodec::from_id(codec as u32) {
+        Some(codec) => codec,
+        None => illegal_argument!(env, "`codec` must be identifier of built-in codec.", 0),
+    };
 
     match page_size {
         page_size if page_size > 0 => config = config.set_max_page_size(page_size as usize),
@@ -55,7 +98,7 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_create(
         ));
     }
 
-    let database: JavaDatabase = Database::new(config);
+    let database: JavaDatabase = SharedDatabase::with_codec(config, RandomState::new(), codec);
 
     Box::into_raw(Box::new(database)) as jlong
 }
@@ -82,7 +125,7 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_get(
     let value = unwrap!(env, database.get(&partition, &key), null_mut());
 
     match value {
-        Some(value) => unwrap!(env, env.byte_array_from_slice(value), null_mut()),
+        Some(value) => unwrap!(env, env.byte_array_from_slice(&value), null_mut()),
         None => null_mut(),
     }
 }
@@ -95,6 +138,79 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_put(
     partition: jbyteArray,
     key: jbyteArray,
     value: jbyteArray,
+    ttl_millis: jlong,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", 0);
+    }
+
+    if value.is_null() {
+        illegal_argument!(env, "Parameter `value` must not be null.", 0);
+    }
+
+    if ttl_millis < 0 {
+        illegal_argument!(env, "`ttl_millis` must be greater or equals to zero.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key = unwrap!(env, env.convert_byte_array(key), 0);
+    let value = unwrap!(env, env.convert_byte_array(value), 0);
+
+    let result = match ttl_millis {
+        0 => database.put(partition, key, value),
+        ttl_millis => database.put_with_ttl(
+            partition,
+            key,
+            value,
+            Duration::from_millis(ttl_millis as u64),
+        ),
+    };
+
+    unwrap!(env, result, 0) as jboolean
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_putIfAbsent(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    value: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", 0);
+    }
+
+    if value.is_null() {
+        illegal_argument!(env, "Parameter `value` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key = unwrap!(env, env.convert_byte_array(key), 0);
+    let value = unwrap!(env, env.convert_byte_array(value), 0);
+
+    unwrap!(env, database.put_if_absent(partition, key, value), 0) as jboolean
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_replaceIfPresent(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    value: jbyteArray,
 ) -> jboolean {
     if partition.is_null() {
         illegal_argument!(env, "Parameter `partition` must not be null.", 0);
@@ -113,7 +229,178 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_put(
     let key = unwrap!(env, env.convert_byte_array(key), 0);
     let value = unwrap!(env, env.convert_byte_array(value), 0);
 
-    unwrap!(env, database.put(partition, key, value), 0) as jboolean
+    unwrap!(env, database.replace_if_present(partition, key, value), 0) as jboolean
+}
+
+/// Null `expected` means missing value, null `value` deletes value.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_compareAndSwap(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    expected: jbyteArray,
+    value: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key = unwrap!(env, env.convert_byte_array(key), 0);
+    let expected = if expected.is_null() {
+        None
+    } else {
+        Some(unwrap!(env, env.convert_byte_array(expected), 0))
+    };
+    let value = if value.is_null() {
+        None
+    } else {
+        Some(unwrap!(env, env.convert_byte_array(value), 0))
+    };
+
+    unwrap!(
+        env,
+        database.compare_and_swap(partition, key, expected.as_ref(), value),
+        0
+    ) as jboolean
+}
+
+/// Adds `delta` to value stored as 8 byte little endian integer, missing value counts as zero.
+/// Returns new value.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_incrementBy(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    delta: jlong,
+) -> jlong {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key = unwrap!(env, env.convert_byte_array(key), 0);
+    let operand = delta.to_le_bytes().to_vec();
+    let value = unwrap!(
+        env,
+        database.merge_with(partition, key, operand, &IntegerAdd),
+        0
+    );
+
+    match value {
+        Some(value) => {
+            let mut bytes = [0; 8];
+
+            bytes.copy_from_slice(&value);
+
+            i64::from_le_bytes(bytes)
+        }
+        None => 0,
+    }
+}
+
+/// Appends `value` to the end of stored value. Returns new value.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_append(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    value: jbyteArray,
+) -> jbyteArray {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", null_mut());
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", null_mut());
+    }
+
+    if value.is_null() {
+        illegal_argument!(env, "Parameter `value` must not be null.", null_mut());
+    }
+
+    let database = database!(env, handle, null_mut());
+    let partition = unwrap!(env, env.convert_byte_array(partition), null_mut());
+    let key = unwrap!(env, env.convert_byte_array(key), null_mut());
+    let value = unwrap!(env, env.convert_byte_array(value), null_mut());
+    let value = unwrap!(
+        env,
+        database.merge_with(partition, key, value, &Concat),
+        null_mut()
+    );
+
+    match value {
+        Some(value) => unwrap!(env, env.byte_array_from_slice(&value), null_mut()),
+        None => null_mut(),
+    }
+}
+
+/// Passes current value or null to `callback` and stores value returned by it, null removes
+/// value. Partition is locked during the call, so `callback` must not access the database.
+/// Returns new value.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_update(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    callback: jobject,
+) -> jbyteArray {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", null_mut());
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", null_mut());
+    }
+
+    if callback.is_null() {
+        illegal_argument!(env, "Parameter `callback` must not be null.", null_mut());
+    }
+
+    let database = database!(env, handle, null_mut());
+    let partition = unwrap!(env, env.convert_byte_array(partition), null_mut());
+    let key = unwrap!(env, env.convert_byte_array(key), null_mut());
+    let callback = JObject::from(callback);
+    let method_apply = unwrap!(
+        env,
+        env.get_method_id(callback, METHOD_CALLBACK_APPLY, SIGNATURE_CALLBACK_APPLY),
+        null_mut()
+    );
+    let result = unwrap!(
+        env,
+        database.update(partition, key, |value| {
+            apply_callback(&env, callback, method_apply, value).map(|_| value.clone())
+        }),
+        null_mut()
+    );
+
+    // Value is not changed and exception thrown by callback propagates to the caller.
+    if let Err(JniError::JavaException) = result {
+        return null_mut();
+    }
+
+    match unwrap!(env, result, null_mut()) {
+        Some(value) => unwrap!(env, env.byte_array_from_slice(&value), null_mut()),
+        None => null_mut(),
+    }
 }
 
 #[no_mangle]
@@ -219,6 +506,136 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_range(
     );
 }
 
+/// Passes at most `limit` entries from `key_first` to `key_last` inclusive to `callback`, entries
+/// start after `after` if it is not null. Returns key to pass as `after` to get the next page or
+/// null if no entries left or `callback` returned `false`.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_rangePage(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key_first: jbyteArray,
+    key_last: jbyteArray,
+    after: jbyteArray,
+    limit: jint,
+    callback: jobject,
+) -> jbyteArray {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", null_mut());
+    }
+
+    if key_first.is_null() {
+        illegal_argument!(env, "Parameter `key_first` must not be null.", null_mut());
+    }
+
+    if key_last.is_null() {
+        illegal_argument!(env, "Parameter `key_last` must not be null.", null_mut());
+    }
+
+    if limit <= 0 {
+        illegal_argument!(env, "Parameter `limit` must be positive.", null_mut());
+    }
+
+    if callback.is_null() {
+        illegal_argument!(env, "Parameter `callback` must not be null.", null_mut());
+    }
+
+    let database = database!(env, handle, null_mut());
+    let partition = unwrap!(env, env.convert_byte_array(partition), null_mut());
+    let key_first = unwrap!(env, env.convert_byte_array(key_first), null_mut());
+    let key_last = unwrap!(env, env.convert_byte_array(key_last), null_mut());
+    let range_start = if after.is_null() {
+        Bound::Included(key_first)
+    } else {
+        let after = unwrap!(env, env.convert_byte_array(after), null_mut());
+
+        if after >= key_first {
+            Bound::Excluded(after)
+        } else {
+            Bound::Included(key_first)
+        }
+    };
+    let callback = JObject::from(callback);
+    let method_accept = unwrap!(
+        env,
+        env.get_method_id(callback, METHOD_CALLBACL_ACCEPT, SIGNATURE_CALLBACL_ACCEPT),
+        null_mut()
+    );
+    let (entries, cursor) = unwrap!(
+        env,
+        database.range_page(
+            &partition,
+            (range_start, Bound::Included(key_last)),
+            limit as usize
+        ),
+        null_mut()
+    );
+
+    for (key, value) in entries {
+        let key = unwrap!(env, env.byte_array_from_slice(&key), null_mut());
+        let value = unwrap!(env, env.byte_array_from_slice(&value), null_mut());
+        let result = unwrap!(
+            env,
+            env.call_method_unchecked(
+                callback,
+                method_accept,
+                JavaType::Primitive(Primitive::Boolean),
+                &[JValue::from(key), JValue::from(value)]
+            ),
+            null_mut()
+        );
+
+        if unwrap!(env, env.exception_check(), null_mut()) || !unwrap!(env, result.z(), null_mut())
+        {
+            return null_mut();
+        }
+    }
+
+    match cursor {
+        Some(cursor) => unwrap!(
+            env,
+            env.byte_array_from_slice(cursor.last_key()),
+            null_mut()
+        ),
+        None => null_mut(),
+    }
+}
+
+/// Deletes values from `key_first` to `key_last` inclusive. Returns number of deleted values.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_deleteRange(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    key_first: jbyteArray,
+    key_last: jbyteArray,
+) -> jlong {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key_first.is_null() {
+        illegal_argument!(env, "Parameter `key_first` must not be null.", 0);
+    }
+
+    if key_last.is_null() {
+        illegal_argument!(env, "Parameter `key_last` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key_first = unwrap!(env, env.convert_byte_array(key_first), 0);
+    let key_last = unwrap!(env, env.convert_byte_array(key_last), 0);
+
+    unwrap!(
+        env,
+        database.delete_range(&partition, key_first..=key_last),
+        0
+    ) as jlong
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ru_snake_htdb_HTDBNative_succ(
     env: JNIEnv,
@@ -245,8 +662,8 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_succ(
     );
 
     if let Some((key, value)) = unwrap!(env, database.succ(&partition, &key), null_mut()) {
-        let key = unwrap!(env, env.byte_array_from_slice(key), null_mut());
-        let value = unwrap!(env, env.byte_array_from_slice(value), null_mut());
+        let key = unwrap!(env, env.byte_array_from_slice(&key), null_mut());
+        let value = unwrap!(env, env.byte_array_from_slice(&value), null_mut());
         let result = unwrap!(
             env,
             env.new_object_unchecked(
@@ -289,8 +706,8 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_pred(
     );
 
     if let Some((key, value)) = unwrap!(env, database.pred(&partition, &key), null_mut()) {
-        let key = unwrap!(env, env.byte_array_from_slice(key), null_mut());
-        let value = unwrap!(env, env.byte_array_from_slice(value), null_mut());
+        let key = unwrap!(env, env.byte_array_from_slice(&key), null_mut());
+        let value = unwrap!(env, env.byte_array_from_slice(&value), null_mut());
         let result = unwrap!(
             env,
             env.new_object_unchecked(
@@ -307,6 +724,107 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_pred(
     }
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_partitions(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jobjectArray {
+    let database = database!(env, handle, null_mut());
+    let partitions = unwrap!(env, database.partitions(), null_mut());
+    let result = unwrap!(
+        env,
+        env.new_object_array(partitions.len() as jsize, CLASS_BYTE_ARRAY, JObject::null()),
+        null_mut()
+    );
+
+    for (index, partition) in partitions.iter().enumerate() {
+        let partition = unwrap!(env, env.byte_array_from_slice(partition), null_mut());
+
+        unwrap!(
+            env,
+            env.set_object_array_element(result, index as jsize, partition),
+            null_mut()
+        );
+    }
+
+    result
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_containsPartition(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+
+    unwrap!(env, database.contains_partition(&partition), 0) as jboolean
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_partitionLength(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+) -> jlong {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+
+    unwrap!(env, database.partition_len(&partition), 0) as jlong
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_dropPartition(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+
+    unwrap!(env, database.drop_partition(&partition), 0) as jboolean
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_renamePartition(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    partition: jbyteArray,
+    new_partition: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if new_partition.is_null() {
+        illegal_argument!(env, "Parameter `new_partition` must not be null.", 0);
+    }
+
+    let database = database!(env, handle, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let new_partition = unwrap!(env, env.convert_byte_array(new_partition), 0);
+
+    unwrap!(env, database.rename_partition(&partition, new_partition), 0) as jboolean
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ru_snake_htdb_HTDBNative_count(
     env: JNIEnv,
@@ -318,6 +836,50 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_count(
     unwrap!(env, database.count(), 0) as jlong
 }
 
+/// Returns statistics as `RawStats`, durations of the last save and load are passed in
+/// nanoseconds or -1 if database was not saved or loaded yet.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_stats(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jobject {
+    let database = database!(env, handle, null_mut());
+    let stats = unwrap!(env, database.stats(), null_mut());
+    let constructor = unwrap!(
+        env,
+        env.get_method_id(CLASS_STATS, METHOD_STATS_INIT, SIGNATURE_STATS_INIT),
+        null_mut()
+    );
+    let nanos = |duration: Option<Duration>| match duration {
+        Some(duration) => duration.as_nanos() as jlong,
+        None => -1,
+    };
+    let result = unwrap!(
+        env,
+        env.new_object_unchecked(
+            CLASS_STATS,
+            constructor,
+            &[
+                JValue::Long(stats.partitions() as jlong),
+                JValue::Long(stats.pages() as jlong),
+                JValue::Long(stats.entries() as jlong),
+                JValue::Double(stats.min_page_fill()),
+                JValue::Double(stats.average_page_fill()),
+                JValue::Double(stats.max_page_fill()),
+                JValue::Long(stats.splits() as jlong),
+                JValue::Long(stats.removed_pages() as jlong),
+                JValue::Long(stats.memory() as jlong),
+                JValue::Long(nanos(stats.last_save())),
+                JValue::Long(nanos(stats.last_load())),
+            ]
+        ),
+        null_mut()
+    );
+
+    result.into_inner()
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ru_snake_htdb_HTDBNative_save(
     env: JNIEnv,
@@ -340,6 +902,122 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_load(
     unwrap!(env, database.load());
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_beginTransaction(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jlong {
+    let database = database!(env, handle, 0);
+
+    Box::into_raw(Box::new(database.begin())) as jlong
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_transactionGet(
+    env: JNIEnv,
+    _class: JClass,
+    transaction: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+) -> jbyteArray {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", null_mut());
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", null_mut());
+    }
+
+    let transaction = transaction!(env, transaction, null_mut());
+    let partition = unwrap!(env, env.convert_byte_array(partition), null_mut());
+    let key = unwrap!(env, env.convert_byte_array(key), null_mut());
+    let value = unwrap!(env, transaction.get(&partition, &key), null_mut());
+
+    match value {
+        Some(value) => unwrap!(env, env.byte_array_from_slice(&value), null_mut()),
+        None => null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_transactionPut(
+    env: JNIEnv,
+    _class: JClass,
+    transaction: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+    value: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", 0);
+    }
+
+    if value.is_null() {
+        illegal_argument!(env, "Parameter `value` must not be null.", 0);
+    }
+
+    let transaction = transaction!(env, transaction, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key = unwrap!(env, env.convert_byte_array(key), 0);
+    let value = unwrap!(env, env.convert_byte_array(value), 0);
+
+    unwrap!(env, transaction.put(partition, key, value), 0) as jboolean
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_transactionDelete(
+    env: JNIEnv,
+    _class: JClass,
+    transaction: jlong,
+    partition: jbyteArray,
+    key: jbyteArray,
+) -> jboolean {
+    if partition.is_null() {
+        illegal_argument!(env, "Parameter `partition` must not be null.", 0);
+    }
+
+    if key.is_null() {
+        illegal_argument!(env, "Parameter `key` must not be null.", 0);
+    }
+
+    let transaction = transaction!(env, transaction, 0);
+    let partition = unwrap!(env, env.convert_byte_array(partition), 0);
+    let key = unwrap!(env, env.convert_byte_array(key), 0);
+
+    unwrap!(env, transaction.delete(&partition, &key), 0) as jboolean
+}
+
+/// Commits transaction and frees its handle, handle is freed even if commit fails.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_commitTransaction(
+    env: JNIEnv,
+    _class: JClass,
+    transaction: jlong,
+) {
+    if transaction == 0 {
+        illegal_argument!(env, "Invalid transaction handle");
+    }
+
+    let transaction = unsafe { Box::from_raw(transaction as *mut JavaTransaction) };
+
+    unwrap!(env, transaction.commit());
+}
+
+/// Discards transaction changes and frees its handle.
+#[no_mangle]
+pub extern "system" fn Java_ru_snake_htdb_HTDBNative_rollbackTransaction(
+    _env: JNIEnv,
+    _class: JClass,
+    transaction: jlong,
+) {
+    drop(unsafe { Box::from_raw(transaction as *mut JavaTransaction) });
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ru_snake_htdb_HTDBNative_destroy(
     _env: JNIEnv,
@@ -348,3 +1026,37 @@ pub extern "system" fn Java_ru_snake_htdb_HTDBNative_destroy(
 ) {
     drop(unsafe { Box::from_raw(handle as *mut JavaDatabase) });
 }
+
+/// Replaces `value` with result of `callback`. If callback throws, value is left unchanged and
+/// `JavaException` error is returned with the exception still pending.
+fn apply_callback(
+    env: &JNIEnv,
+    callback: JObject,
+    method_apply: JMethodID,
+    value: &mut Option<Vec<u8>>,
+) -> JniResult<()> {
+    let current = match value {
+        Some(value) => JObject::from(env.byte_array_from_slice(value)?),
+        None => JObject::null(),
+    };
+    let result = env
+        .call_method_unchecked(
+            callback,
+            method_apply,
+            JavaType::Array(Box::new(JavaType::Primitive(Primitive::Byte))),
+            &[JValue::from(current)],
+        )?
+        .l()?;
+
+    if env.exception_check()? {
+        return Err(JniError::JavaException);
+    }
+
+    *value = if result.is_null() {
+        None
+    } else {
+        Some(env.convert_byte_array(result.into_inner())?)
+    };
+
+    Ok(())
+}
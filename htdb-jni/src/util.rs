@@ -17,19 +17,34 @@ macro_rules! illegal_argument {
 
 macro_rules! database {
     ($env:ident, $handle:ident) => {
-        match unsafe { ($handle as *mut JavaDatabase).as_mut() } {
+        match unsafe { ($handle as *const JavaDatabase).as_ref() } {
             Some(database) => database,
             None => illegal_argument!($env, "Invalid database handle"),
         }
     };
     ($env:ident, $handle:ident, $result:expr) => {
-        match unsafe { ($handle as *mut JavaDatabase).as_mut() } {
+        match unsafe { ($handle as *const JavaDatabase).as_ref() } {
             Some(database) => database,
             None => illegal_argument!($env, "Invalid database handle", $result),
         }
     };
 }
 
+macro_rules! transaction {
+    ($env:ident, $handle:ident) => {
+        match unsafe { ($handle as *mut JavaTransaction).as_mut() } {
+            Some(transaction) => transaction,
+            None => illegal_argument!($env, "Invalid transaction handle"),
+        }
+    };
+    ($env:ident, $handle:ident, $result:expr) => {
+        match unsafe { ($handle as *mut JavaTransaction).as_mut() } {
+            Some(transaction) => transaction,
+            None => illegal_argument!($env, "Invalid transaction handle", $result),
+        }
+    };
+}
+
 macro_rules! unwrap {
     ($env:ident, $expression:expr) => {
         match $expression {
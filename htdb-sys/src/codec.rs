@@ -0,0 +1,222 @@
+use crate::DatabaseError;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::io::Read;
+use std::io::Write;
+
+/// Encoding of snapshot payload. Codec identifier is recorded in snapshot header, so encoding can
+/// change without misreading existing files. Snapshot of built-in codec is loaded by database
+/// using any codec, snapshot of other codec only by database using codec with the same
+/// identifier. Identifiers below 256 are reserved for built-in codecs.
+pub trait Codec: Debug {
+    fn id(&self) -> u32;
+
+    fn encode<W, T>(&self, writer: W, value: &T) -> Result<(), DatabaseError>
+    where
+        W: Write,
+        T: Serialize + ?Sized;
+
+    fn decode<R, T>(&self, reader: R) -> Result<T, DatabaseError>
+    where
+        R: Read,
+        T: DeserializeOwned;
+}
+
+/// Compact Rust specific encoding provided by bincode 1.x, used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+/// MessagePack encoding, structures are written as maps with field names.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+/// CBOR encoding as defined by RFC 8949.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+impl Codec for Bincode {
+    fn id(&self) -> u32 {
+        0
+    }
+
+    fn encode<W, T>(&self, writer: W, value: &T) -> Result<(), DatabaseError>
+    where
+        W: Write,
+        T: Serialize + ?Sized,
+    {
+        bincode::serialize_into(writer, value).map_err(DatabaseError::serialize_error)
+    }
+
+    fn decode<R, T>(&self, reader: R) -> Result<T, DatabaseError>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        // Same options as `bincode::deserialize_from`, payload may be followed by padding
+        // written by compressor.
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .deserialize_from(reader)
+            .map_err(DatabaseError::serialize_error)
+    }
+}
+
+impl Codec for MessagePack {
+    fn id(&self) -> u32 {
+        1
+    }
+
+    fn encode<W, T>(&self, mut writer: W, value: &T) -> Result<(), DatabaseError>
+    where
+        W: Write,
+        T: Serialize + ?Sized,
+    {
+        rmp_serde::encode::write_named(&mut writer, value).map_err(DatabaseError::codec_error)
+    }
+
+    fn decode<R, T>(&self, reader: R) -> Result<T, DatabaseError>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        rmp_serde::decode::from_read(reader).map_err(DatabaseError::codec_error)
+    }
+}
+
+impl Codec for Cbor {
+    fn id(&self) -> u32 {
+        2
+    }
+
+    fn encode<W, T>(&self, writer: W, value: &T) -> Result<(), DatabaseError>
+    where
+        W: Write,
+        T: Serialize + ?Sized,
+    {
+        ciborium::ser::into_writer(value, writer).map_err(DatabaseError::codec_error)
+    }
+
+    fn decode<R, T>(&self, reader: R) -> Result<T, DatabaseError>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        ciborium::de::from_reader(reader).map_err(DatabaseError::codec_error)
+    }
+}
+
+/// Any of built-in codecs selected at runtime, used where codec type can not be chosen at compile
+/// time, for example by JNI library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinCodec {
+    Bincode,
+    MessagePack,
+    Cbor,
+}
+
+impl BuiltinCodec {
+    /// Returns built-in codec with identifier `id` or `None` if there is no such codec.
+    pub fn from_id(id: u32) -> Option<BuiltinCodec> {
+        [
+            BuiltinCodec::Bincode,
+            BuiltinCodec::MessagePack,
+            BuiltinCodec::Cbor,
+        ]
+        .into_iter()
+        .find(|codec| codec.id() == id)
+    }
+}
+
+impl Codec for BuiltinCodec {
+    fn id(&self) -> u32 {
+        match self {
+            BuiltinCodec::Bincode => Bincode.id(),
+            BuiltinCodec::MessagePack => MessagePack.id(),
+            BuiltinCodec::Cbor => Cbor.id(),
+        }
+    }
+
+    fn encode<W, T>(&self, writer: W, value: &T) -> Result<(), DatabaseError>
+    where
+        W: Write,
+        T: Serialize + ?Sized,
+    {
+        match self {
+            BuiltinCodec::Bincode => Bincode.encode(writer, value),
+            BuiltinCodec::MessagePack => MessagePack.encode(writer, value),
+            BuiltinCodec::Cbor => Cbor.encode(writer, value),
+        }
+    }
+
+    fn decode<R, T>(&self, reader: R) -> Result<T, DatabaseError>
+    where
+        R: Read,
+        T: DeserializeOwned,
+    {
+        match self {
+            BuiltinCodec::Bincode => Bincode.decode(reader),
+            BuiltinCodec::MessagePack => MessagePack.decode(reader),
+            BuiltinCodec::Cbor => Cbor.decode(reader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Bincode;
+    use crate::codec::BuiltinCodec;
+    use crate::codec::Cbor;
+    use crate::codec::Codec;
+    use crate::codec::MessagePack;
+    use std::collections::BTreeMap;
+
+    fn roundtrip<C>(codec: C)
+    where
+        C: Codec,
+    {
+        let value: BTreeMap<String, Vec<u8>> = (0..10)
+            .map(|index| (format!("key-{}", index), vec![index; index as usize]))
+            .collect();
+        let mut bytes = Vec::new();
+
+        codec.encode(&mut bytes, &value).unwrap();
+
+        let decoded: BTreeMap<String, Vec<u8>> = codec.decode(&bytes[..]).unwrap();
+
+        assert_eq!(value, decoded);
+    }
+
+    #[test]
+    fn codecs_must_decode_encoded_values() {
+        roundtrip(Bincode);
+        roundtrip(MessagePack);
+        roundtrip(Cbor);
+        roundtrip(BuiltinCodec::MessagePack);
+    }
+
+    #[test]
+    fn builtin_codec_must_be_found_by_id() {
+        for codec in [
+            BuiltinCodec::Bincode,
+            BuiltinCodec::MessagePack,
+            BuiltinCodec::Cbor,
+        ] {
+            assert_eq!(Some(codec), BuiltinCodec::from_id(codec.id()));
+        }
+
+        assert_eq!(None, BuiltinCodec::from_id(256));
+    }
+
+    #[test]
+    fn bincode_must_match_bincode_serialize() {
+        let value = (1u32, "text".to_string(), vec![1u64, 2, 3]);
+        let mut bytes = Vec::new();
+
+        Bincode.encode(&mut bytes, &value).unwrap();
+
+        assert_eq!(bincode::serialize(&value).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,23 @@
+/// Compression of snapshot payload. Codec is recorded in snapshot header, so snapshot is always
+/// decoded with the codec it was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// Deflate with level from 0 (fastest) to 9 (smallest).
+    Deflate(u32),
+    /// Zstandard with level from 1 (fastest) to 22 (smallest), 0 selects default level.
+    Zstd(i32),
+    Lz4,
+}
+
+impl Compression {
+    /// Returns compression identifier written to snapshot header.
+    pub(crate) fn compression_id(&self) -> u32 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate(_) => 1,
+            Compression::Zstd(_) => 2,
+            Compression::Lz4 => 3,
+        }
+    }
+}
@@ -1,13 +1,33 @@
+use crate::Compression;
+use serde::Deserialize;
+use serde::Serialize;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 const MAX_PAGE_SIZE: usize = 128;
+const MIN_PAGE_FILL: f64 = 0.25;
 const MAX_PAGES: Option<usize> = None;
+const MAX_PAGE_BYTES: Option<usize> = None;
+const MEMORY_BUDGET: Option<usize> = None;
+const WRITE_AHEAD_LOG: bool = false;
+const SYNC_WRITE_AHEAD_LOG: bool = true;
+const EXPIRY_SWEEP_INTERVAL: Option<Duration> = None;
+const COMPRESSION: Compression = Compression::Deflate(6);
+const TYPE_TAG: Option<u32> = None;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
     max_page_size: usize,
+    min_page_fill: f64,
     max_pages: Option<usize>,
+    max_page_bytes: Option<usize>,
+    memory_budget: Option<usize>,
     storage_path: PathBuf,
+    write_ahead_log: bool,
+    sync_write_ahead_log: bool,
+    expiry_sweep_interval: Option<Duration>,
+    compression: Compression,
+    type_tag: Option<u32>,
 }
 
 impl Config {
@@ -20,6 +40,24 @@ impl Config {
         self.max_page_size
     }
 
+    /// Sets minimal fraction of `max_page_size` entries per page. Page with fewer entries will be
+    /// merged with adjacent page if they fit into single page. Fraction is clamped to range from
+    /// 0.0 to 1.0, NaN resets it to default.
+    pub fn set_min_page_fill(mut self, min_page_fill: f64) -> Self {
+        self.min_page_fill = if min_page_fill.is_nan() {
+            MIN_PAGE_FILL
+        } else {
+            min_page_fill.clamp(0.0, 1.0)
+        };
+        self
+    }
+
+    pub fn min_page_fill(&self) -> f64 {
+        self.min_page_fill
+    }
+
+    /// Sets number of pages of the whole database kept in memory, least recently used pages of
+    /// any partition are swapped out when it is exceeded.
     pub fn set_max_pages(mut self, max_pages: Option<usize>) -> Self {
         self.max_pages = max_pages;
         self
@@ -29,6 +67,29 @@ impl Config {
         self.max_pages
     }
 
+    /// Sets approximate size of page in bytes as estimated by `EstimateSize`. Page is split when
+    /// either its number of entries or its size exceeds the limit, pages are merged only if both
+    /// fit. `None` limits pages by number of entries only.
+    pub fn set_max_page_bytes(mut self, max_page_bytes: Option<usize>) -> Self {
+        self.max_page_bytes = max_page_bytes;
+        self
+    }
+
+    pub fn max_page_bytes(&self) -> Option<usize> {
+        self.max_page_bytes
+    }
+
+    /// Sets approximate size in bytes of resident pages of the whole database. Least recently used
+    /// pages of any partition are swapped out while either the budget or `max_pages` is exceeded.
+    pub fn set_memory_budget(mut self, memory_budget: Option<usize>) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
     pub fn set_storage_path<P>(mut self, storage_path: P) -> Self
     where
         P: AsRef<Path>,
@@ -40,14 +101,155 @@ impl Config {
     pub fn storage_path(&self) -> &Path {
         self.storage_path.as_path()
     }
+
+    pub fn set_write_ahead_log(mut self, write_ahead_log: bool) -> Self {
+        self.write_ahead_log = write_ahead_log;
+        self
+    }
+
+    pub fn write_ahead_log(&self) -> bool {
+        self.write_ahead_log
+    }
+
+    /// Sets whether every write-ahead log record is synced to disk before write returns. Not
+    /// synced records survive crash of the process, but may be lost on crash of the system.
+    pub fn set_sync_write_ahead_log(mut self, sync_write_ahead_log: bool) -> Self {
+        self.sync_write_ahead_log = sync_write_ahead_log;
+        self
+    }
+
+    pub fn sync_write_ahead_log(&self) -> bool {
+        self.sync_write_ahead_log
+    }
+
+    /// Sets how often expired entries are removed from all partitions. Expired entries are
+    /// invisible anyway, sweep only frees memory. `None`, the default, disables periodic sweep
+    /// and expired entries are removed when they are accessed.
+    pub fn set_expiry_sweep_interval(mut self, expiry_sweep_interval: Option<Duration>) -> Self {
+        self.expiry_sweep_interval = expiry_sweep_interval;
+        self
+    }
+
+    pub fn expiry_sweep_interval(&self) -> Option<Duration> {
+        self.expiry_sweep_interval
+    }
+
+    /// Sets compression of saved snapshots. Snapshot saved with any compression can be loaded.
+    /// Deflate level is clamped to range from 0 to 9.
+    pub fn set_compression(mut self, compression: Compression) -> Self {
+        self.compression = match compression {
+            Compression::Deflate(level) => Compression::Deflate(level.min(9)),
+            compression => compression,
+        };
+        self
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Overrides identifier of partition, key and value types recorded in snapshot. Snapshot is
+    /// loaded only by database with the same identifier. `None`, the default, derives it from
+    /// type names, which change when types are renamed or moved.
+    pub fn set_type_tag(mut self, type_tag: Option<u32>) -> Self {
+        self.type_tag = type_tag;
+        self
+    }
+
+    pub fn type_tag(&self) -> Option<u32> {
+        self.type_tag
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             max_page_size: MAX_PAGE_SIZE,
+            min_page_fill: MIN_PAGE_FILL,
             max_pages: MAX_PAGES,
+            max_page_bytes: MAX_PAGE_BYTES,
+            memory_budget: MEMORY_BUDGET,
             storage_path: PathBuf::from("."),
+            write_ahead_log: WRITE_AHEAD_LOG,
+            sync_write_ahead_log: SYNC_WRITE_AHEAD_LOG,
+            expiry_sweep_interval: EXPIRY_SWEEP_INTERVAL,
+            compression: COMPRESSION,
+            type_tag: TYPE_TAG,
+        }
+    }
+}
+
+/// Part of configuration stored in snapshot, it defines how data was split into pages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedConfig {
+    max_page_size: usize,
+}
+
+/// Outcome of successful load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStatus {
+    Loaded,
+    /// Snapshot was saved with other `Config::max_page_size`, its pages were split and merged to
+    /// follow the current configuration.
+    Repaged {
+        saved_page_size: usize,
+    },
+}
+
+impl SavedConfig {
+    pub fn max_page_size(&self) -> usize {
+        self.max_page_size
+    }
+}
+
+impl From<&Config> for SavedConfig {
+    fn from(config: &Config) -> Self {
+        SavedConfig {
+            max_page_size: config.max_page_size,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Compression;
+    use crate::Config;
+
+    #[test]
+    fn set_min_page_fill_must_clamp_fraction() {
+        assert_eq!(
+            0.0,
+            Config::default().set_min_page_fill(-1.0).min_page_fill()
+        );
+        assert_eq!(
+            1.0,
+            Config::default().set_min_page_fill(2.5).min_page_fill()
+        );
+        assert_eq!(
+            0.5,
+            Config::default().set_min_page_fill(0.5).min_page_fill()
+        );
+        assert_eq!(
+            0.25,
+            Config::default()
+                .set_min_page_fill(f64::NAN)
+                .min_page_fill()
+        );
+    }
+
+    #[test]
+    fn set_compression_must_clamp_deflate_level() {
+        assert_eq!(
+            Compression::Deflate(9),
+            Config::default()
+                .set_compression(Compression::Deflate(10))
+                .compression()
+        );
+        assert_eq!(
+            Compression::Deflate(0),
+            Config::default()
+                .set_compression(Compression::Deflate(0))
+                .compression()
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::ops::Bound;
+use std::ops::RangeBounds;
+
+/// Entries returned by `Database::range_page` and cursor to the next page, cursor is `None` when
+/// no entries left.
+pub type RangePage<K, V> = (Vec<(K, V)>, Option<Cursor<K>>);
+
+/// Position after the last entry returned by `Database::range_page`. Cursor is a range from the
+/// last returned key (exclusive) to the end of the requested range, it is passed back to
+/// `range_page` to get the next page. Only the key is kept, so cursor stays valid after pages
+/// are split or merged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor<K> {
+    last_key: K,
+    range_end: Bound<K>,
+}
+
+impl<K> Cursor<K> {
+    pub fn new(last_key: K, range_end: Bound<K>) -> Cursor<K> {
+        Cursor {
+            last_key,
+            range_end,
+        }
+    }
+
+    pub fn last_key(&self) -> &K {
+        &self.last_key
+    }
+
+    pub fn range_end(&self) -> Bound<&K> {
+        self.range_end.as_ref()
+    }
+}
+
+impl<K> RangeBounds<K> for Cursor<K> {
+    fn start_bound(&self) -> Bound<&K> {
+        Bound::Excluded(&self.last_key)
+    }
+
+    fn end_bound(&self) -> Bound<&K> {
+        self.range_end.as_ref()
+    }
+}
@@ -1,79 +1,308 @@
 use crate::config::Config;
-use crate::hasher::TrivialHasherBuilder;
+use crate::config::LoadStatus;
+use crate::config::SavedConfig;
+use crate::cursor::Cursor;
+use crate::cursor::RangePage;
+use crate::expiry;
+use crate::pages::Counters;
+use crate::pages::LegacyPages;
 use crate::pages::Pages;
+use crate::pages::Residency;
+use crate::snapshot;
+use crate::stats::Stats;
+use crate::transaction::Transaction;
+use crate::transaction::Undo;
+use crate::transaction::Writes;
 use crate::visiter::TreeVisiter;
+use crate::wal::LogRecord;
+use crate::wal::WriteAheadLog;
+use crate::Bincode;
+use crate::Codec;
 use crate::DatabaseError;
+use crate::EstimateSize;
 use crate::HashTreeVisiter;
-use flate2::read::DeflateDecoder;
-use flate2::write::DeflateEncoder;
-use flate2::Compression;
+use crate::MergeOperator;
+use crate::PrefixKey;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::fs;
 use std::fs::File;
+use std::hash::BuildHasher;
 use std::hash::Hash;
-use std::io::BufReader;
-use std::io::BufWriter;
-use std::rc::Rc;
+use std::ops::Bound;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
+const SNAPSHOT_FILE: &str = "full.htdb";
+const SNAPSHOT_TEMP_FILE: &str = "full.htdb.tmp";
+const LOG_FILE: &str = "wal.htdb";
+
+type Partitions<H, K, V> = HashMap<H, Pages<K, V>>;
+type SnapshotData<H, K, V> = (SavedConfig, Partitions<H, K, V>);
+type LoadedSnapshot<H, K, V> = (Partitions<H, K, V>, LoadStatus);
+
+/// Single threaded database. Partitions are kept in hash map built by `S`, default SipHash
+/// based hasher withstands arbitrary partition keys, `TrivialHasherBuilder` is faster for
+/// integer partition keys. Snapshots are encoded by `C`.
 #[derive(Debug)]
-pub struct Database<H, K, V>
+pub struct Database<H, K, V, S = RandomState, C = Bincode>
 where
     K: Ord,
 {
-    config: Rc<Config>,
-    map: HashMap<H, Pages<K, V>, TrivialHasherBuilder>,
+    config: Arc<Config>,
+    codec: C,
+    map: HashMap<H, Pages<K, V>, S>,
+    /// Number of entries, splits and removed pages in all partitions, kept up to date by pages.
+    counters: Arc<Counters>,
+    log: Option<WriteAheadLog>,
+    last_sweep: Instant,
+    last_save: Option<Duration>,
+    last_load: Option<Duration>,
+    merge_operator: Option<Arc<dyn MergeOperator<V>>>,
 }
 
 impl<H, K, V> Database<H, K, V>
 where
     H: Eq + Hash + Serialize + DeserializeOwned + Debug,
-    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug,
-    V: Default + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
 {
     pub fn new(config: Config) -> Database<H, K, V> {
+        Database::with_config(Arc::new(config))
+    }
+
+    pub(crate) fn with_config(config: Arc<Config>) -> Database<H, K, V> {
+        Database::build(config, RandomState::new(), Bincode)
+    }
+
+    /// Creates database and, if write-ahead log is enabled, restores its state from the last
+    /// snapshot and the log.
+    pub fn open(config: Config) -> Result<Database<H, K, V>, DatabaseError> {
+        Database::open_with_hasher(config, RandomState::new())
+    }
+}
+
+impl<H, K, V, S> Database<H, K, V, S>
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    S: BuildHasher,
+{
+    /// Creates database which hashes partition keys with hashers built by `hash_builder`.
+    pub fn with_hasher(config: Config, hash_builder: S) -> Database<H, K, V, S> {
+        Database::with_codec(config, hash_builder, Bincode)
+    }
+
+    /// Same as `open` but hashes partition keys with hashers built by `hash_builder`.
+    pub fn open_with_hasher(
+        config: Config,
+        hash_builder: S,
+    ) -> Result<Database<H, K, V, S>, DatabaseError> {
+        Database::open_with_codec(config, hash_builder, Bincode)
+    }
+
+    /// Creates database which counts its resident pages in `residency` shared with other
+    /// databases, so `Config::max_pages` limits all of them together.
+    pub(crate) fn with_residency(
+        config: Arc<Config>,
+        hash_builder: S,
+        residency: Arc<Residency>,
+    ) -> Database<H, K, V, S> {
+        let mut database = Database::build(config, hash_builder, Bincode);
+
+        database.counters = Arc::new(Counters::with_residency(residency));
+        database
+    }
+}
+
+impl<H, K, V, S, C> Database<H, K, V, S, C>
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    S: BuildHasher,
+    C: Codec,
+{
+    /// Creates database which encodes snapshots with `codec`. Snapshots of built-in codecs are
+    /// loaded by any codec, snapshot of other codec only by codec with the same identifier.
+    pub fn with_codec(config: Config, hash_builder: S, codec: C) -> Database<H, K, V, S, C> {
+        Database::build(Arc::new(config), hash_builder, codec)
+    }
+
+    /// Same as `open_with_hasher` but encodes snapshots with `codec`.
+    pub fn open_with_codec(
+        config: Config,
+        hash_builder: S,
+        codec: C,
+    ) -> Result<Database<H, K, V, S, C>, DatabaseError> {
+        let mut database = Database::with_codec(config, hash_builder, codec);
+
+        if database.config.write_ahead_log() {
+            database.load()?;
+        }
+
+        Ok(database)
+    }
+
+    fn build(config: Arc<Config>, hash_builder: S, codec: C) -> Database<H, K, V, S, C> {
         Database {
-            config: Rc::new(config),
-            map: HashMap::default(),
+            config,
+            codec,
+            map: HashMap::with_hasher(hash_builder),
+            counters: Arc::default(),
+            log: None,
+            last_sweep: Instant::now(),
+            last_save: None,
+            last_load: None,
+            merge_operator: None,
         }
     }
 
     pub fn get(&mut self, hash_key: &H, tree_key: &K) -> Result<Option<&V>, DatabaseError> {
-        if let Some(pages) = self.map.get(hash_key) {
-            Ok(pages.get(tree_key))
+        if let Some(pages) = self.partition_mut(hash_key)? {
+            pages.get(tree_key)
         } else {
             Ok(None)
         }
     }
 
+    /// Same as `get` but returns `None` instead of loading swapped out page.
+    pub(crate) fn try_get(&self, hash_key: &H, tree_key: &K) -> Option<Option<&V>> {
+        match self.map.get(hash_key) {
+            Some(pages) => pages.try_get(tree_key),
+            None => Some(None),
+        }
+    }
+
     pub fn put(&mut self, hash_key: H, tree_key: K, data: V) -> Result<bool, DatabaseError> {
-        let pages = self
-            .map
-            .entry(hash_key)
-            .or_insert_with(|| Pages::new(self.config.clone()));
+        self.sweep_if_due()?;
+        self.append_log(&LogRecord::Put {
+            hash_key: &hash_key,
+            tree_key: &tree_key,
+            data: &data,
+        })?;
+
+        let pages = self.partition_entry(hash_key)?;
+
+        pages.insert(tree_key, data)
+    }
+
+    /// Puts value which is removed after `ttl`. Expired value is invisible to all reads even if
+    /// it was not removed yet.
+    pub fn put_with_ttl(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        data: V,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError> {
+        self.put_with_expiry(hash_key, tree_key, data, expiry::expires_at(ttl))
+    }
+
+    /// Same as `put_with_ttl` but takes absolute expiry time in milliseconds since UNIX epoch.
+    pub(crate) fn put_with_expiry(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        data: V,
+        expires_at: u64,
+    ) -> Result<bool, DatabaseError> {
+        self.sweep_if_due()?;
+        self.append_log(&LogRecord::PutWithExpiry {
+            hash_key: &hash_key,
+            tree_key: &tree_key,
+            data: &data,
+            expires_at,
+        })?;
+
+        let pages = self.partition_entry(hash_key)?;
+
+        pages.insert_with_expiry(tree_key, data, expires_at)
+    }
+
+    /// Puts value only if partition does not contain `tree_key`. Returns `true` if value was put.
+    pub fn put_if_absent(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        data: V,
+    ) -> Result<bool, DatabaseError> {
+        if self.contains(&hash_key, &tree_key)? {
+            return Ok(false);
+        }
+
+        self.put(hash_key, tree_key, data)
+    }
+
+    /// Replaces value only if partition contains `tree_key`. Returns `true` if value was replaced.
+    pub fn replace_if_present(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        data: V,
+    ) -> Result<bool, DatabaseError> {
+        if !self.contains(&hash_key, &tree_key)? {
+            return Ok(false);
+        }
+
+        self.put(hash_key, tree_key, data).map(|_| true)
+    }
 
-        let replaced = pages.insert(tree_key, data);
+    /// Sets value to `new` only if current value equals to `expected`, `None` means missing value
+    /// in both cases. Returns `true` if value was changed.
+    pub fn compare_and_swap(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        expected: Option<&V>,
+        new: Option<V>,
+    ) -> Result<bool, DatabaseError>
+    where
+        V: PartialEq,
+    {
+        if self.get(&hash_key, &tree_key)? != expected {
+            return Ok(false);
+        }
 
-        Ok(replaced)
+        match new {
+            Some(data) => self.put(hash_key, tree_key, data)?,
+            None => self.delete(&hash_key, &tree_key)?,
+        };
+
+        Ok(true)
     }
 
     pub fn contains(&mut self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
-        if let Some(pages) = self.map.get(hash_key) {
-            Ok(pages.contains(tree_key))
+        if let Some(pages) = self.partition_mut(hash_key)? {
+            pages.contains(tree_key)
         } else {
             Ok(false)
         }
     }
 
-    pub fn delete(&mut self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
-        if let Some(pages) = self.map.get_mut(hash_key) {
-            Ok(pages.remove(tree_key))
-        } else {
-            Ok(false)
+    /// Same as `contains` but returns `None` instead of loading swapped out page.
+    pub(crate) fn try_contains(&self, hash_key: &H, tree_key: &K) -> Option<bool> {
+        match self.map.get(hash_key) {
+            Some(pages) => pages.try_contains(tree_key),
+            None => Some(false),
         }
     }
 
+    pub fn delete(&mut self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        self.sweep_if_due()?;
+        self.append_log(&LogRecord::<_, _, &V>::Delete { hash_key, tree_key })?;
+
+        self.take(hash_key, tree_key).map(|data| data.is_some())
+    }
+
     pub fn range<F>(
         &mut self,
         hash_key: &H,
@@ -82,7 +311,7 @@ where
         callback: F,
     ) -> Result<(), DatabaseError>
     where
-        F: Fn(&K, &V) -> bool,
+        F: FnMut(&K, &V) -> bool,
     {
         if tree_first > tree_last {
             return Err(DatabaseError::invalid_range(
@@ -90,71 +319,2049 @@ where
             ));
         }
 
-        if let Some(pages) = self.map.get(hash_key) {
-            pages.range(tree_first, tree_last, callback);
-
-            Ok(())
+        if let Some(pages) = self.partition_mut(hash_key)? {
+            pages.range(tree_first, tree_last, callback)
         } else {
             Ok(())
         }
     }
 
+    /// Same as `range` but returns `None` without calling `callback` if any page in range is
+    /// swapped out.
+    pub(crate) fn try_range<F>(
+        &self,
+        hash_key: &H,
+        tree_first: &K,
+        tree_last: &K,
+        callback: F,
+    ) -> Option<Result<(), DatabaseError>>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        if tree_first > tree_last {
+            return Some(Err(DatabaseError::invalid_range(
+                "Invalid range, first must be less or equals to last",
+            )));
+        }
+
+        match self.map.get(hash_key) {
+            Some(pages) => pages.try_range(tree_first, tree_last, callback).map(Ok),
+            None => Some(Ok(())),
+        }
+    }
+
+    /// Returns at most `limit` entries of partition within `range` in key order and cursor which
+    /// resumes right after the last returned entry.
+    pub fn range_page<R>(
+        &mut self,
+        hash_key: &H,
+        range: R,
+        limit: usize,
+    ) -> Result<RangePage<K, V>, DatabaseError>
+    where
+        R: RangeBounds<K>,
+        V: Clone,
+    {
+        if limit == 0 {
+            return Err(DatabaseError::invalid_range(
+                "Invalid limit, limit must be greater than zero",
+            ));
+        }
+
+        let range_end = range.end_bound().cloned();
+
+        collect_page(self.range_iter(hash_key, range), limit, range_end)
+    }
+
+    /// Same as `range_page` but returns `None` if it reaches swapped out page.
+    pub(crate) fn try_range_page<R>(
+        &self,
+        hash_key: &H,
+        range: R,
+        limit: usize,
+    ) -> Option<Result<RangePage<K, V>, DatabaseError>>
+    where
+        R: RangeBounds<K>,
+        V: Clone,
+    {
+        if limit == 0 {
+            return Some(Err(DatabaseError::invalid_range(
+                "Invalid limit, limit must be greater than zero",
+            )));
+        }
+
+        let range_end = range.end_bound().cloned();
+        let entries = match self.map.get(hash_key) {
+            Some(pages) => pages.try_range_iter(range),
+            None => return Some(Ok((Vec::new(), None))),
+        };
+
+        let entries = entries.map(|entry| {
+            entry
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .ok_or(())
+        });
+
+        collect_page(entries, limit, range_end).ok().map(Ok)
+    }
+
+    /// Deletes all values of partition within `range`. Returns number of deleted values.
+    pub fn delete_range<R>(&mut self, hash_key: &H, range: R) -> Result<usize, DatabaseError>
+    where
+        R: RangeBounds<K>,
+    {
+        if !self.map.contains_key(hash_key) {
+            return Ok(0);
+        }
+
+        self.sweep_if_due()?;
+        self.append_log(&LogRecord::<_, _, &V>::DeleteRange {
+            hash_key,
+            range_start: range.start_bound(),
+            range_end: range.end_bound(),
+        })?;
+        self.remove_range(hash_key, range)
+    }
+
+    /// Returns iterator over copies of entries of partition in `range`. Iterator can be
+    /// reversed, inverted range is treated as empty. Swapped out pages are loaded when iteration
+    /// reaches them, so iterator returns error if page can not be loaded. Pages already passed
+    /// by iterator are swapped out as usual, so iteration is bounded by `Config::max_pages`.
+    pub fn range_iter<R>(
+        &mut self,
+        hash_key: &H,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = Result<(K, V), DatabaseError>> + '_
+    where
+        R: RangeBounds<K>,
+        V: Clone,
+    {
+        // Error of freeing pages of other partitions is returned as the first item.
+        let (error, pages) = match self.partition_mut(hash_key) {
+            Ok(pages) => (None, pages),
+            Err(error) => (Some(error), None),
+        };
+
+        error.into_iter().map(Err).chain(
+            pages
+                .map(|pages| pages.range_iter(range))
+                .into_iter()
+                .flatten(),
+        )
+    }
+
+    /// Returns iterator over copies of entries of partition which keys start with `prefix`.
+    /// Pages are loaded when iteration reaches them, same as by `range_iter`.
+    pub fn scan_prefix<'a>(
+        &'a mut self,
+        hash_key: &H,
+        prefix: &'a K::Prefix,
+    ) -> impl Iterator<Item = Result<(K, V), DatabaseError>> + 'a
+    where
+        K: PrefixKey,
+        V: Clone,
+    {
+        // Error of freeing pages of other partitions is returned as the first item.
+        let (error, pages) = match self.partition_mut(hash_key) {
+            Ok(pages) => (None, pages),
+            Err(error) => (Some(error), None),
+        };
+
+        error.into_iter().map(Err).chain(
+            pages
+                .map(|pages| pages.scan_prefix(prefix))
+                .into_iter()
+                .flatten(),
+        )
+    }
+
     pub fn succ(&mut self, hash_key: &H, tree_key: &K) -> Result<Option<(&K, &V)>, DatabaseError> {
-        if let Some(pages) = self.map.get(hash_key) {
-            Ok(pages.succ(tree_key))
+        if let Some(pages) = self.partition_mut(hash_key)? {
+            pages.succ(tree_key)
         } else {
             Ok(None)
         }
     }
 
+    /// Same as `succ` but returns `None` instead of loading swapped out pages.
+    pub(crate) fn try_succ(&self, hash_key: &H, tree_key: &K) -> Option<Option<(&K, &V)>> {
+        match self.map.get(hash_key) {
+            Some(pages) => pages.try_succ(tree_key),
+            None => Some(None),
+        }
+    }
+
     pub fn pred(&mut self, hash_key: &H, tree_key: &K) -> Result<Option<(&K, &V)>, DatabaseError> {
-        if let Some(pages) = self.map.get(hash_key) {
-            Ok(pages.pred(tree_key))
+        if let Some(pages) = self.partition_mut(hash_key)? {
+            pages.pred(tree_key)
         } else {
             Ok(None)
         }
     }
 
-    pub fn count(&mut self) -> Result<usize, DatabaseError> {
-        Ok(self.map.values().map(Pages::size).sum())
+    /// Same as `pred` but returns `None` instead of loading swapped out pages.
+    pub(crate) fn try_pred(&self, hash_key: &H, tree_key: &K) -> Option<Option<(&K, &V)>> {
+        match self.map.get(hash_key) {
+            Some(pages) => pages.try_pred(tree_key),
+            None => Some(None),
+        }
+    }
+
+    /// Returns hash keys of all non-empty partitions in arbitrary order.
+    pub fn partitions(&self) -> impl Iterator<Item = &H> {
+        self.map.keys()
     }
 
+    pub fn contains_partition(&self, hash_key: &H) -> bool {
+        self.map.contains_key(hash_key)
+    }
+
+    /// Returns number of entries in partition, expired entries are counted until they are
+    /// removed.
+    pub fn partition_len(&self, hash_key: &H) -> usize {
+        self.map.get(hash_key).map(Pages::size).unwrap_or(0)
+    }
+
+    /// Returns number of entries in partition which are not expired. Unlike `partition_len` it
+    /// checks expiry time of every entry with time-to-live in partition.
+    pub fn live_partition_len(&self, hash_key: &H) -> usize {
+        self.map
+            .get(hash_key)
+            .map(|pages| pages.live_size(expiry::now()))
+            .unwrap_or(0)
+    }
+
+    /// Removes partition with all its entries. Returns `false` if partition does not exist.
+    pub fn drop_partition(&mut self, hash_key: &H) -> Result<bool, DatabaseError> {
+        if !self.map.contains_key(hash_key) {
+            return Ok(false);
+        }
+
+        self.append_log(&LogRecord::<_, &K, &V>::DropPartition { hash_key })?;
+
+        Ok(self.map.remove(hash_key).is_some())
+    }
+
+    /// Moves all entries of partition to `new_hash_key`, partition with this key is replaced.
+    /// Returns `false` if partition does not exist.
+    pub fn rename_partition(
+        &mut self,
+        hash_key: &H,
+        new_hash_key: H,
+    ) -> Result<bool, DatabaseError> {
+        if !self.map.contains_key(hash_key) {
+            return Ok(false);
+        }
+
+        self.append_log(&LogRecord::<_, &K, &V>::RenamePartition {
+            hash_key,
+            new_hash_key: &new_hash_key,
+        })?;
+
+        match self.map.remove(hash_key) {
+            Some(pages) => {
+                self.map.insert(new_hash_key, pages);
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Returns number of entries in all partitions, expired entries are counted until they are
+    /// removed.
+    pub fn count(&self) -> Result<usize, DatabaseError> {
+        Ok(self.counters.entries())
+    }
+
+    /// Returns number of entries in all partitions which are not expired. Unlike `count` it
+    /// checks expiry time of every entry with time-to-live in database.
+    pub fn live_count(&self) -> Result<usize, DatabaseError> {
+        let now = expiry::now();
+
+        Ok(self.map.values().map(|pages| pages.live_size(now)).sum())
+    }
+
+    /// Returns number of partitions, pages and entries, page fill, page splits and removals since
+    /// database was created, approximate memory and duration of the last save and load.
+    pub fn stats(&self) -> Result<Stats, DatabaseError> {
+        let mut stats = Stats::new(self.config.max_page_size(), &self.counters);
+
+        for pages in self.map.values() {
+            stats.add_partition(pages);
+        }
+
+        stats.set_durations(self.last_save, self.last_load);
+
+        Ok(stats)
+    }
+
+    /// Removes expired entries from all partitions. Returns number of removed entries.
+    pub fn remove_expired(&mut self) -> Result<usize, DatabaseError> {
+        let now = expiry::now();
+        let mut removed = 0;
+
+        for pages in self.map.values_mut() {
+            removed += pages.remove_expired(now)?;
+        }
+
+        self.map.retain(|_, pages| !pages.is_empty());
+        self.last_sweep = Instant::now();
+
+        Ok(removed)
+    }
+
+    /// Merges under-filled pages in all partitions.
+    pub fn compact(&mut self) -> Result<(), DatabaseError> {
+        for pages in self.map.values_mut() {
+            pages.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes snapshot to temporary file and atomically replaces the previous snapshot with it,
+    /// so interrupted save never damages already saved data.
     pub fn save(&mut self) -> Result<(), DatabaseError> {
-        let path = self.config.storage_path().join("full.htdb");
-        let file = File::create(path).map_err(DatabaseError::create_file_error)?;
-        let writer = BufWriter::new(file);
-        let mut encoder = DeflateEncoder::new(writer, Compression::default());
+        let start = Instant::now();
+
+        // Log must be checked before snapshot replaces records it may contain.
+        Self::open_log(&self.config, &mut self.log)?;
+        Self::save_snapshot(&self.config, &self.codec, &self.map, self.count()?)?;
+
+        if let Some(log) = &mut self.log {
+            log.truncate()?;
+        }
 
-        bincode::serialize_into(&mut encoder, &self.map).map_err(DatabaseError::serialize_error)?;
-        encoder.finish().map_err(DatabaseError::encoding_error)?;
+        self.last_save = Some(start.elapsed());
 
         Ok(())
     }
 
-    pub fn load(&mut self) -> Result<(), DatabaseError> {
-        let path = self.config.storage_path().join("full.htdb");
-        let file = File::open(path).map_err(DatabaseError::open_file_error)?;
-        let reader = BufReader::new(file);
-        let mut decoder = DeflateDecoder::new(reader);
+    /// Loads the last saved snapshot. If write-ahead log is enabled, missing snapshot is treated
+    /// as empty database and mutations from the log are applied on top of it. Database is left
+    /// unchanged if snapshot can not be read.
+    pub fn load(&mut self) -> Result<LoadStatus, DatabaseError> {
+        let start = Instant::now();
+        let snapshot = Self::load_snapshot(&self.config, &self.codec)?;
+        let mut status = LoadStatus::Loaded;
 
+        self.log = None;
         self.map.clear();
 
-        let data: HashMap<H, Pages<K, V>> =
-            bincode::deserialize_from(&mut decoder).map_err(DatabaseError::serialize_error)?;
+        if let Some((data, snapshot_status)) = snapshot {
+            for (hash_key, pages) in data {
+                self.insert_partition(hash_key, pages);
+            }
+
+            status = snapshot_status;
+        }
 
-        self.map.extend(data.into_iter());
+        if self.config.write_ahead_log() {
+            let log = WriteAheadLog::open(&log_path(&self.config), |record| self.apply(record))?;
 
-        Ok(())
+            self.log = Some(log.set_sync(self.config.sync_write_ahead_log()));
+        }
+
+        self.last_load = Some(start.elapsed());
+
+        Ok(status)
     }
 
-    pub fn visit<T>(&self, visiter: &mut T)
+    pub fn visit<T>(&self, visiter: &mut T) -> Result<(), DatabaseError>
     where
         T: HashTreeVisiter<H, K, V> + TreeVisiter<K, V>,
     {
         for (hash, page) in &self.map {
             visiter.visit_hash_before(hash);
-            page.visit(visiter);
+            page.visit(visiter)?;
             visiter.visit_hash_after(hash);
         }
+
+        Ok(())
+    }
+
+    /// Sets operator used by `merge`, replacing previous one.
+    pub fn set_merge_operator<M>(&mut self, operator: M)
+    where
+        M: MergeOperator<V> + 'static,
+    {
+        self.merge_operator = Some(Arc::new(operator));
+    }
+
+    pub(crate) fn merge_operator(&self) -> Option<Arc<dyn MergeOperator<V>>> {
+        self.merge_operator.clone()
+    }
+
+    pub(crate) fn set_shared_merge_operator(&mut self, operator: Arc<dyn MergeOperator<V>>) {
+        self.merge_operator = Some(operator);
+    }
+
+    /// Inserts partition taken from another database, its entries are counted by this database.
+    pub(crate) fn insert_partition(&mut self, hash_key: H, mut pages: Pages<K, V>) {
+        pages.set_counters(self.counters.clone());
+        self.map.insert(hash_key, pages);
+    }
+
+    pub(crate) fn partition_map(&self) -> &HashMap<H, Pages<K, V>, S> {
+        &self.map
+    }
+
+    pub(crate) fn partition_map_mut(&mut self) -> &mut HashMap<H, Pages<K, V>, S> {
+        &mut self.map
+    }
+
+    /// Writes `partitions` to temporary file and renames it to snapshot file.
+    pub(crate) fn save_snapshot<T>(
+        config: &Config,
+        codec: &C,
+        partitions: &T,
+        entries: usize,
+    ) -> Result<(), DatabaseError>
+    where
+        T: Serialize,
+    {
+        let storage_path = config.storage_path();
+        let path = storage_path.join(SNAPSHOT_FILE);
+        let temp_path = storage_path.join(SNAPSHOT_TEMP_FILE);
+        let data = (SavedConfig::from(config), partitions);
+
+        fs::create_dir_all(storage_path).map_err(DatabaseError::create_file_error)?;
+
+        if let Err(error) = snapshot::write_snapshot(
+            &temp_path,
+            Self::type_tag(config),
+            entries,
+            config.compression(),
+            codec,
+            &data,
+        ) {
+            fs::remove_file(&temp_path).ok();
+
+            return Err(error);
+        }
+
+        fs::rename(&temp_path, &path).map_err(DatabaseError::create_file_error)?;
+        sync_directory(storage_path)
+    }
+
+    /// Reads partitions from snapshot file, attaches `config` to them and counts their entries.
+    /// Entries are counted by detached counters until partitions are inserted into database.
+    /// Pages saved with other page size are split and merged to follow `config`. Returns `None`
+    /// if write-ahead log is enabled and snapshot was never saved. Snapshot saved before header
+    /// was introduced is read as legacy snapshot without header checks.
+    pub(crate) fn load_snapshot(
+        config: &Arc<Config>,
+        codec: &C,
+    ) -> Result<Option<LoadedSnapshot<H, K, V>>, DatabaseError> {
+        let path = config.storage_path().join(SNAPSHOT_FILE);
+        let temp_path = config.storage_path().join(SNAPSHOT_TEMP_FILE);
+
+        // Temporary file is left only by interrupted save, complete snapshot was not replaced.
+        fs::remove_file(temp_path).ok();
+
+        if config.write_ahead_log() && !path.exists() {
+            return Ok(None);
+        }
+
+        if !snapshot::has_header(&path)? {
+            return Self::load_legacy_snapshot(config, &path).map(Some);
+        }
+
+        let ((saved_config, mut data), entries): (SnapshotData<H, K, V>, _) =
+            snapshot::read_snapshot(&path, Self::type_tag(config), codec)?;
+
+        for pages in data.values_mut() {
+            pages.set_config(config.clone());
+            pages.recount();
+        }
+
+        if data.values().map(Pages::size).sum::<usize>() != entries {
+            return Err(DatabaseError::corrupted_snapshot(
+                "Number of entries in snapshot does not match its header",
+            ));
+        }
+
+        if saved_config.max_page_size() == config.max_page_size() {
+            return Ok(Some((data, LoadStatus::Loaded)));
+        }
+
+        for pages in data.values_mut() {
+            pages.repage()?;
+        }
+
+        let status = LoadStatus::Repaged {
+            saved_page_size: saved_config.max_page_size(),
+        };
+
+        Ok(Some((data, status)))
+    }
+
+    /// Returns type tag recorded in snapshot, overridden by `config` or derived from types.
+    fn type_tag(config: &Config) -> u32 {
+        config
+            .type_tag()
+            .unwrap_or_else(snapshot::type_tag::<H, K, V>)
+    }
+
+    /// Reads partitions saved before snapshot header was introduced. Page size was not saved
+    /// with them, so pages are always split and merged to follow `config`.
+    fn load_legacy_snapshot(
+        config: &Arc<Config>,
+        path: &Path,
+    ) -> Result<LoadedSnapshot<H, K, V>, DatabaseError> {
+        let legacy: HashMap<H, LegacyPages<K, V>> = snapshot::read_legacy_snapshot(path)?;
+        let mut data = HashMap::with_capacity(legacy.len());
+
+        for (hash_key, legacy_pages) in legacy {
+            let mut pages = Pages::from_legacy(config.clone(), legacy_pages);
+
+            pages.recount();
+            pages.repage()?;
+            data.insert(hash_key, pages);
+        }
+
+        Ok((data, LoadStatus::Loaded))
+    }
+
+    /// Returns partition after swapping out pages of other partitions if database exceeds
+    /// `Config::max_pages`.
+    fn partition_mut(&mut self, hash_key: &H) -> Result<Option<&mut Pages<K, V>>, DatabaseError> {
+        self.reclaim(Some(hash_key))?;
+
+        Ok(self.map.get_mut(hash_key))
+    }
+
+    /// Same as `partition_mut` but creates missing partition.
+    fn partition_entry(&mut self, hash_key: H) -> Result<&mut Pages<K, V>, DatabaseError> {
+        self.reclaim(Some(&hash_key))?;
+
+        Ok(self
+            .map
+            .entry(hash_key)
+            .or_insert_with(|| Pages::with_counters(self.config.clone(), self.counters.clone())))
+    }
+
+    /// Swaps out least recently used pages of partitions other than `except` while resident
+    /// pages of database exceed `Config::max_pages`. Accessed partition swaps out its own pages.
+    pub(crate) fn reclaim(&mut self, except: Option<&H>) -> Result<(), DatabaseError> {
+        let residency = self.counters.residency();
+
+        if !residency.exceeds(&self.config) {
+            return Ok(());
+        }
+
+        for (hash_key, pages) in self.map.iter_mut() {
+            if Some(hash_key) == except {
+                continue;
+            }
+
+            pages.reclaim()?;
+
+            if !residency.exceeds(&self.config) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes expired entries if `Config::expiry_sweep_interval` passed since the last sweep.
+    fn sweep_if_due(&mut self) -> Result<(), DatabaseError> {
+        match self.config.expiry_sweep_interval() {
+            Some(interval) if self.last_sweep.elapsed() >= interval => {
+                self.remove_expired().map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn append_log(&mut self, record: &LogRecord<&H, &K, &V>) -> Result<(), DatabaseError> {
+        Self::write_log(&self.config, &mut self.log, record)
+    }
+
+    /// Appends record to write-ahead log, log is opened on the first write.
+    fn write_log(
+        config: &Config,
+        log: &mut Option<WriteAheadLog>,
+        record: &LogRecord<&H, &K, &V>,
+    ) -> Result<(), DatabaseError> {
+        Self::open_log(config, log)?;
+
+        match log {
+            Some(log) => log.append(record),
+            None => Ok(()),
+        }
+    }
+
+    /// Opens write-ahead log of database created by `new` instead of `open`, which fails if the
+    /// log contains records which were not replayed.
+    pub(crate) fn open_log(
+        config: &Config,
+        log: &mut Option<WriteAheadLog>,
+    ) -> Result<(), DatabaseError> {
+        if config.write_ahead_log() && log.is_none() {
+            *log = Some(
+                WriteAheadLog::create::<H, K, V>(&log_path(config))?
+                    .set_sync(config.sync_write_ahead_log()),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Removes values within `range` from partition. Partition is removed with its last value.
+    fn remove_range<R>(&mut self, hash_key: &H, range: R) -> Result<usize, DatabaseError>
+    where
+        R: RangeBounds<K>,
+    {
+        let pages = match self.partition_mut(hash_key)? {
+            Some(pages) => pages,
+            None => return Ok(0),
+        };
+        let removed = pages.remove_range(range)?;
+
+        if pages.is_empty() {
+            self.map.remove(hash_key);
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes value from partition and returns it. Partition is removed with its last value.
+    fn take(&mut self, hash_key: &H, tree_key: &K) -> Result<Option<V>, DatabaseError> {
+        let pages = match self.partition_mut(hash_key)? {
+            Some(pages) => pages,
+            None => return Ok(None),
+        };
+        let result = pages.remove(tree_key)?;
+
+        if pages.is_empty() {
+            self.map.remove(hash_key);
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) fn apply(&mut self, record: LogRecord<H, K, V>) -> Result<(), DatabaseError> {
+        match record {
+            LogRecord::Put {
+                hash_key,
+                tree_key,
+                data,
+            } => {
+                let pages = self.partition_entry(hash_key)?;
+
+                pages.insert(tree_key, data)?;
+            }
+            LogRecord::Delete { hash_key, tree_key } => {
+                self.take(&hash_key, &tree_key)?;
+            }
+            LogRecord::PutWithExpiry {
+                hash_key,
+                tree_key,
+                data,
+                expires_at,
+            } => {
+                let pages = self.partition_entry(hash_key)?;
+
+                pages.insert_with_expiry(tree_key, data, expires_at)?;
+            }
+            LogRecord::DeleteRange {
+                hash_key,
+                range_start,
+                range_end,
+            } => {
+                self.remove_range(&hash_key, (range_start, range_end))?;
+            }
+            LogRecord::DropPartition { hash_key } => {
+                self.map.remove(&hash_key);
+            }
+            LogRecord::RenamePartition {
+                hash_key,
+                new_hash_key,
+            } => {
+                if let Some(pages) = self.map.remove(&hash_key) {
+                    self.map.insert(new_hash_key, pages);
+                }
+            }
+            LogRecord::Batch(records) => {
+                for record in records {
+                    self.apply(record)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<H, K, V, S, C> Database<H, K, V, S, C>
+where
+    H: Eq + Hash + Clone + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    S: BuildHasher,
+    C: Codec,
+{
+    /// Starts transaction. Changes made in transaction are invisible until it is committed and
+    /// discarded if transaction is rolled back or dropped.
+    pub fn begin(&mut self) -> Transaction<'_, H, K, V, S, C> {
+        Transaction::new(self)
+    }
+
+    /// Applies all transaction changes. Changes are written to write-ahead log as single record.
+    /// If any change can not be applied, already applied changes are reverted, `RevertError` is
+    /// returned if revert fails too.
+    pub(crate) fn commit(&mut self, writes: Writes<H, K, V>) -> Result<(), DatabaseError> {
+        let records = writes
+            .iter()
+            .flat_map(|(hash_key, tree)| {
+                tree.iter().map(move |(tree_key, data)| {
+                    Self::log_record(hash_key, tree_key, data.as_ref())
+                })
+            })
+            .collect();
+
+        self.append_log(&LogRecord::Batch(records))?;
+
+        let mut undo = Vec::new();
+
+        match self.apply_writes(writes, &mut undo) {
+            Ok(()) => Ok(()),
+            Err(error) => match self.revert(undo) {
+                Ok(()) => Err(error),
+                Err(revert_error) => Err(DatabaseError::revert_error(error, revert_error)),
+            },
+        }
+    }
+
+    /// Applies transaction changes without logging them, stops at the first failed change.
+    /// Replaced values of applied changes are pushed to `undo`.
+    pub(crate) fn apply_writes(
+        &mut self,
+        writes: Writes<H, K, V>,
+        undo: &mut Undo<H, K, V>,
+    ) -> Result<(), DatabaseError> {
+        for (hash_key, tree) in writes {
+            for (tree_key, data) in tree {
+                let expires_at = self
+                    .map
+                    .get(&hash_key)
+                    .and_then(|pages| pages.expires_at(&tree_key));
+                let previous = self.write(hash_key.clone(), tree_key.clone(), data)?;
+
+                undo.push((hash_key.clone(), tree_key, previous, expires_at));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts all `entries` into partition. Entries sorted by key and following all existing keys
+    /// are packed into full pages without splitting, other entries are put one by one. Every page
+    /// worth of entries is written to write-ahead log as single record. Returns number of added
+    /// keys.
+    pub fn bulk_load<I>(&mut self, hash_key: H, entries: I) -> Result<usize, DatabaseError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.sweep_if_due()?;
+
+        let chunk_size = self.config.max_page_size().max(1);
+        let mut entries = entries.into_iter().peekable();
+        let mut added = 0;
+
+        while entries.peek().is_some() {
+            let chunk: Vec<_> = entries.by_ref().take(chunk_size).collect();
+            let records = chunk
+                .iter()
+                .map(|(tree_key, data)| Self::log_record(&hash_key, tree_key, Some(data)))
+                .collect();
+
+            self.append_log(&LogRecord::Batch(records))?;
+
+            added += self.partition_entry(hash_key.clone())?.bulk_load(chunk)?;
+        }
+
+        Ok(added)
+    }
+
+    /// Passes current value to `f` and stores value left in the option after the call, so value can
+    /// be changed in place, inserted or removed with single lookup. Expiry time of changed value is
+    /// kept. If write-ahead log is enabled, `f` is called with copy of the value and the result is
+    /// written to the log before it is applied, unchanged value is not logged.
+    pub fn update<F, T>(&mut self, hash_key: H, tree_key: K, f: F) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(&mut Option<V>) -> T,
+        V: Clone + PartialEq,
+    {
+        self.sweep_if_due()?;
+
+        if self.config.write_ahead_log() {
+            let config = self.config.clone();
+            // Log is moved out while partition is borrowed and put back even if update fails.
+            let mut log = self.log.take();
+            let result = self.update_with_log(hash_key, tree_key, f, |record| {
+                Self::write_log(&config, &mut log, record)
+            });
+
+            self.log = log;
+
+            return result;
+        }
+
+        let result = self
+            .partition_entry(hash_key.clone())?
+            .update(tree_key, f)?;
+
+        if self
+            .map
+            .get(&hash_key)
+            .is_some_and(|pages| pages.is_empty())
+        {
+            self.map.remove(&hash_key);
+        }
+
+        Ok(result)
+    }
+
+    /// Same as `update` but passes changed value to `log` before it is applied, `f` is called
+    /// with copy of the value. Unchanged value is neither logged nor applied.
+    pub(crate) fn update_with_log<F, T, L>(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        f: F,
+        log: L,
+    ) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(&mut Option<V>) -> T,
+        L: FnOnce(&LogRecord<&H, &K, &V>) -> Result<(), DatabaseError>,
+        V: Clone + PartialEq,
+    {
+        let pages = self.partition_entry(hash_key.clone())?;
+        let previous = pages.get(&tree_key)?.cloned();
+        let mut value = previous.clone();
+        let result = f(&mut value);
+
+        if previous != value {
+            let expires_at = pages.expires_at(&tree_key);
+            let record = match (&value, expires_at) {
+                (Some(data), Some(expires_at)) => LogRecord::PutWithExpiry {
+                    hash_key: &hash_key,
+                    tree_key: &tree_key,
+                    data,
+                    expires_at,
+                },
+                (data, _) => Self::log_record(&hash_key, &tree_key, data.as_ref()),
+            };
+
+            log(&record)?;
+
+            match (value, expires_at) {
+                (Some(data), Some(expires_at)) => {
+                    pages.insert_with_expiry(tree_key, data, expires_at)?;
+                }
+                (Some(data), None) => {
+                    pages.insert(tree_key, data)?;
+                }
+                (None, _) => {
+                    pages.remove(&tree_key)?;
+                }
+            }
+        }
+
+        if pages.is_empty() {
+            self.map.remove(&hash_key);
+        }
+
+        Ok(result)
+    }
+
+    /// Combines current value with `operand` using operator set by `set_merge_operator`. Returns
+    /// merged value.
+    pub fn merge(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        operand: V,
+    ) -> Result<Option<&V>, DatabaseError>
+    where
+        V: Clone + PartialEq,
+    {
+        let operator = self
+            .merge_operator
+            .clone()
+            .ok_or_else(|| DatabaseError::merge_error("Merge operator is not set"))?;
+
+        self.merge_with(hash_key, tree_key, operand, operator.as_ref())
+    }
+
+    /// Combines current value with `operand` using given operator. Returns merged value.
+    pub fn merge_with<M>(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        operand: V,
+        operator: &M,
+    ) -> Result<Option<&V>, DatabaseError>
+    where
+        M: MergeOperator<V> + ?Sized,
+        V: Clone + PartialEq,
+    {
+        let (hash_copy, tree_copy) = (hash_key.clone(), tree_key.clone());
+
+        self.update(hash_key, tree_key, |value| operator.merge(value, operand))??;
+        self.get(&hash_copy, &tree_copy)
+    }
+
+    /// Restores values replaced by partially applied transaction together with their expiry
+    /// times. Restored values are logged too, so replay of the log leaves database in the same
+    /// state. Every value is restored even if some of them fail, the first error is returned.
+    pub(crate) fn revert(&mut self, undo: Undo<H, K, V>) -> Result<(), DatabaseError> {
+        let mut result = self.append_log(&LogRecord::Batch(Self::undo_records(&undo)));
+
+        for (hash_key, tree_key, data, expires_at) in undo.into_iter().rev() {
+            let restored = match (data, expires_at) {
+                (Some(data), Some(expires_at)) => self
+                    .partition_entry(hash_key)
+                    .and_then(|pages| pages.insert_with_expiry(tree_key, data, expires_at))
+                    .map(|_| ()),
+                (data, _) => self.write(hash_key, tree_key, data).map(|_| ()),
+            };
+
+            if let Err(error) = restored {
+                result = result.and(Err(error));
+            }
+        }
+
+        result
+    }
+
+    /// Returns log records restoring values replaced by transaction, latest change first.
+    pub(crate) fn undo_records(undo: &Undo<H, K, V>) -> Vec<LogRecord<&H, &K, &V>> {
+        undo.iter()
+            .rev()
+            .map(
+                |(hash_key, tree_key, data, expires_at)| match (data, expires_at) {
+                    (Some(data), Some(expires_at)) => LogRecord::PutWithExpiry {
+                        hash_key,
+                        tree_key,
+                        data,
+                        expires_at: *expires_at,
+                    },
+                    (data, _) => Self::log_record(hash_key, tree_key, data.as_ref()),
+                },
+            )
+            .collect()
+    }
+
+    /// Puts value if `data` is `Some`, otherwise deletes it. Returns previous value.
+    fn write(
+        &mut self,
+        hash_key: H,
+        tree_key: K,
+        data: Option<V>,
+    ) -> Result<Option<V>, DatabaseError> {
+        match data {
+            Some(data) => self.partition_entry(hash_key)?.replace(tree_key, data),
+            None => self.take(&hash_key, &tree_key),
+        }
+    }
+
+    pub(crate) fn log_record<'a>(
+        hash_key: &'a H,
+        tree_key: &'a K,
+        data: Option<&'a V>,
+    ) -> LogRecord<&'a H, &'a K, &'a V> {
+        match data {
+            Some(data) => LogRecord::Put {
+                hash_key,
+                tree_key,
+                data,
+            },
+            None => LogRecord::Delete { hash_key, tree_key },
+        }
+    }
+}
+
+/// Collects at most `limit` entries and cursor which resumes after them. Entries are read up to
+/// the first entry after the page, so pages loaded on demand beyond it stay untouched.
+fn collect_page<K, V, I, E>(
+    mut entries: I,
+    limit: usize,
+    range_end: Bound<K>,
+) -> Result<RangePage<K, V>, E>
+where
+    K: Clone,
+    I: Iterator<Item = Result<(K, V), E>>,
+{
+    let mut page = Vec::new();
+
+    for entry in entries.by_ref() {
+        page.push(entry?);
+
+        if page.len() == limit {
+            break;
+        }
+    }
+
+    let cursor = match (entries.next().transpose()?, page.last()) {
+        (Some(_), Some((last_key, _))) => Some(Cursor::new(last_key.clone(), range_end)),
+        _ => None,
+    };
+
+    Ok((page, cursor))
+}
+
+pub(crate) fn log_path(config: &Config) -> PathBuf {
+    config.storage_path().join(LOG_FILE)
+}
+
+/// Makes rename of snapshot file durable.
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> Result<(), DatabaseError> {
+    File::open(path)
+        .and_then(|directory| directory.sync_all())
+        .map_err(DatabaseError::create_file_error)
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> Result<(), DatabaseError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::visiter::TreeVisiter;
+    use crate::Cbor;
+    use crate::Compression;
+    use crate::Concat;
+    use crate::Config;
+    use crate::Database;
+    use crate::DatabaseError;
+    use crate::HashTreeVisiter;
+    use crate::IntegerAdd;
+    use crate::LoadStatus;
+    use crate::MessagePack;
+    use crate::TrivialHasherBuilder;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression as DeflateLevel;
+    use std::collections::hash_map::RandomState;
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::ops::Bound;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::Duration;
+
+    fn storage_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("htdb-database-{}-{}", name, process::id()));
+
+        fs::remove_dir_all(&path).ok();
+
+        path
+    }
+
+    #[derive(Default)]
+    struct PageSizeVisiter {
+        sizes: Vec<usize>,
+    }
+
+    impl HashTreeVisiter<u32, u32, u32> for PageSizeVisiter {}
+
+    impl TreeVisiter<u32, u32> for PageSizeVisiter {
+        fn visit_page_before(&mut self, _index: usize, _range_start: &u32, _range_end: &u32) {
+            self.sizes.push(0);
+        }
+
+        fn visit_value(&mut self, _key: &u32, _value: &u32) {
+            if let Some(size) = self.sizes.last_mut() {
+                *size += 1;
+            }
+        }
+    }
+
+    fn open(path: &Path) -> Database<u32, u32, u32> {
+        let config = Config::default()
+            .set_storage_path(path)
+            .set_write_ahead_log(true);
+
+        Database::open(config).unwrap()
+    }
+
+    #[test]
+    fn open_must_replay_write_ahead_log() {
+        let path = storage_path("replay");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.put(1, 2, 20).unwrap();
+        database.delete(&1, &1).unwrap();
+
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(None, database.get(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
+        assert_eq!(1, database.count().unwrap());
+    }
+
+    #[test]
+    fn open_must_apply_write_ahead_log_after_snapshot() {
+        let path = storage_path("snapshot");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+        database.put(1, 2, 20).unwrap();
+
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(Some(&10), database.get(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
+        assert_eq!(2, database.count().unwrap());
+    }
+
+    #[test]
+    fn range_iter_must_support_all_bounds() {
+        let config = Config::default().set_max_page_size(3);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        for index in 0..10 {
+            database.put(1, index, index * 10).unwrap();
+        }
+
+        let keys = |database: &mut Database<u32, u32, u32>, range: (Bound<u32>, Bound<u32>)| {
+            database
+                .range_iter(&1, range)
+                .map(|entry| entry.unwrap().0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![2, 3, 4, 5],
+            keys(&mut database, (Bound::Included(2), Bound::Excluded(6)))
+        );
+        assert_eq!(
+            vec![3, 4, 5, 6],
+            keys(&mut database, (Bound::Excluded(2), Bound::Included(6)))
+        );
+        assert_eq!(
+            vec![0, 1, 2],
+            keys(&mut database, (Bound::Unbounded, Bound::Included(2)))
+        );
+        assert_eq!(
+            vec![8, 9],
+            keys(&mut database, (Bound::Included(8), Bound::Unbounded))
+        );
+        assert_eq!(
+            Vec::<u32>::new(),
+            keys(&mut database, (Bound::Included(6), Bound::Excluded(2)))
+        );
+        assert_eq!(10, database.range_iter(&1, ..).count());
+        assert_eq!(0, database.range_iter(&2, ..).count());
+    }
+
+    #[test]
+    fn range_iter_must_iterate_in_reverse() {
+        let config = Config::default().set_max_page_size(3);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        for index in 0..10 {
+            database.put(1, index, index * 10).unwrap();
+        }
+
+        let entries: Vec<_> = database
+            .range_iter(&1, 2..=7)
+            .rev()
+            .take(3)
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(vec![(7, 70), (6, 60), (5, 50)], entries);
+    }
+
+    #[test]
+    fn scan_prefix_must_return_matching_keys() {
+        let config = Config::default().set_max_page_size(2);
+        let mut database: Database<u32, Vec<u8>, u32> = Database::new(config);
+
+        for (index, key) in [vec![1], vec![1, 2], vec![1, 2, 3], vec![1, 3], vec![2]]
+            .into_iter()
+            .enumerate()
+        {
+            database.put(1, key, index as u32).unwrap();
+        }
+
+        let entries: Vec<_> = database
+            .scan_prefix(&1, &[1, 2])
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(vec![(vec![1, 2], 1), (vec![1, 2, 3], 2)], entries);
+        assert_eq!(0, database.scan_prefix(&2, &[1]).count());
+    }
+
+    #[test]
+    fn partitions_must_list_non_empty_partitions() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+        database.put(1, 2, 20).unwrap();
+        database.put(2, 1, 30).unwrap();
+        database.put(3, 1, 40).unwrap();
+        database.delete(&3, &1).unwrap();
+
+        let mut partitions: Vec<_> = database.partitions().cloned().collect();
+
+        partitions.sort_unstable();
+
+        assert_eq!(vec![1, 2], partitions);
+        assert_eq!(2, database.partition_len(&1));
+        assert_eq!(0, database.partition_len(&3));
+        assert_eq!(false, database.contains_partition(&3));
+    }
+
+    #[test]
+    fn rename_partition_must_replace_target() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+        database.put(2, 2, 20).unwrap();
+
+        assert_eq!(true, database.rename_partition(&1, 2).unwrap());
+        assert_eq!(false, database.rename_partition(&1, 3).unwrap());
+        assert_eq!(false, database.contains_partition(&1));
+        assert_eq!(Some(&10), database.get(&2, &1).unwrap());
+        assert_eq!(None, database.get(&2, &2).unwrap());
+        assert_eq!(true, database.drop_partition(&2).unwrap());
+        assert_eq!(false, database.drop_partition(&2).unwrap());
+        assert_eq!(0, database.count().unwrap());
+    }
+
+    #[test]
+    fn open_must_replay_partition_changes() {
+        let path = storage_path("partitions");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.put(2, 2, 20).unwrap();
+        database.rename_partition(&1, 3).unwrap();
+        database.drop_partition(&2).unwrap();
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(vec![&3], database.partitions().collect::<Vec<_>>());
+        assert_eq!(Some(&10), database.get(&3, &1).unwrap());
+    }
+
+    #[test]
+    fn put_with_ttl_must_hide_expired_entries() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+        database
+            .put_with_ttl(1, 2, 20, Duration::from_secs(0))
+            .unwrap();
+        database
+            .put_with_ttl(1, 3, 30, Duration::from_secs(3600))
+            .unwrap();
+
+        assert_eq!(None, database.get(&1, &2).unwrap());
+        assert_eq!(Some(&30), database.get(&1, &3).unwrap());
+        assert_eq!(Some((&3, &30)), database.succ(&1, &1).unwrap());
+        assert_eq!(
+            vec![1, 3],
+            database
+                .range_iter(&1, ..)
+                .map(|entry| entry.unwrap().0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn live_count_must_skip_expired_entries() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+        database
+            .put_with_ttl(1, 2, 20, Duration::from_secs(0))
+            .unwrap();
+        database
+            .put_with_ttl(2, 1, 10, Duration::from_secs(0))
+            .unwrap();
+
+        assert_eq!(1, database.live_count().unwrap());
+        assert_eq!(1, database.live_partition_len(&1));
+        assert_eq!(0, database.live_partition_len(&2));
+        assert_eq!(3, database.count().unwrap());
+        assert_eq!(2, database.partition_len(&1));
+
+        database.remove_expired().unwrap();
+
+        assert_eq!(1, database.live_count().unwrap());
+        assert_eq!(1, database.count().unwrap());
+    }
+
+    #[test]
+    fn revert_must_keep_expiry_times() {
+        let path = storage_path("revert-expiry");
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let mut database: Database<u32, u32, u32> = Database::open(config.clone()).unwrap();
+
+        database
+            .put_with_ttl(1, 1, 10, Duration::from_secs(3600))
+            .unwrap();
+        database
+            .put_with_ttl(1, 2, 20, Duration::from_secs(3600))
+            .unwrap();
+
+        let deadlines = (
+            database.map[&1].expires_at(&1),
+            database.map[&1].expires_at(&2),
+        );
+        let writes = HashMap::from([(1, BTreeMap::from([(1, Some(11)), (2, None)]))]);
+        let mut undo = Vec::new();
+
+        database.apply_writes(writes, &mut undo).unwrap();
+        database.revert(undo).unwrap();
+
+        assert_eq!(Some(&10), database.get(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
+        assert_eq!(true, deadlines.0.is_some() && deadlines.1.is_some());
+        assert_eq!(deadlines.0, database.map[&1].expires_at(&1));
+        assert_eq!(deadlines.1, database.map[&1].expires_at(&2));
+
+        drop(database);
+
+        let database: Database<u32, u32, u32> = Database::open(config).unwrap();
+
+        assert_eq!(deadlines.0, database.map[&1].expires_at(&1));
+        assert_eq!(deadlines.1, database.map[&1].expires_at(&2));
+    }
+
+    #[test]
+    fn load_must_keep_expiry_times() {
+        let path = storage_path("expiry");
+        let config = Config::default().set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config.clone());
+
+        database
+            .put_with_ttl(1, 1, 10, Duration::from_secs(0))
+            .unwrap();
+        database
+            .put_with_ttl(1, 2, 20, Duration::from_secs(3600))
+            .unwrap();
+        database.save().unwrap();
+
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        database.load().unwrap();
+
+        assert_eq!(false, database.contains(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
+        assert_eq!(0, database.remove_expired().unwrap());
+
+        database.put(1, 2, 21).unwrap();
+        database
+            .put_with_ttl(1, 3, 30, Duration::from_secs(0))
+            .unwrap();
+
+        assert_eq!(1, database.remove_expired().unwrap());
+        assert_eq!(1, database.count().unwrap());
+    }
+
+    #[test]
+    fn open_must_replay_expiry_times() {
+        let path = storage_path("expiry-log");
+        let mut database = open(&path);
+
+        database
+            .put_with_ttl(1, 1, 10, Duration::from_secs(0))
+            .unwrap();
+        database
+            .put_with_ttl(1, 2, 20, Duration::from_secs(3600))
+            .unwrap();
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(None, database.get(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
+    }
+
+    #[test]
+    fn conditional_writes_must_check_current_value() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        assert_eq!(false, database.replace_if_present(1, 1, 10).unwrap());
+        assert_eq!(true, database.put_if_absent(1, 1, 10).unwrap());
+        assert_eq!(false, database.put_if_absent(1, 1, 20).unwrap());
+        assert_eq!(true, database.replace_if_present(1, 1, 30).unwrap());
+        assert_eq!(Some(&30), database.get(&1, &1).unwrap());
+
+        assert_eq!(
+            false,
+            database
+                .compare_and_swap(1, 1, Some(&10), Some(40))
+                .unwrap()
+        );
+        assert_eq!(
+            true,
+            database
+                .compare_and_swap(1, 1, Some(&30), Some(40))
+                .unwrap()
+        );
+        assert_eq!(
+            false,
+            database
+                .compare_and_swap(1, 2, Some(&40), Some(50))
+                .unwrap()
+        );
+        assert_eq!(
+            true,
+            database.compare_and_swap(1, 2, None, Some(50)).unwrap()
+        );
+        assert_eq!(
+            true,
+            database.compare_and_swap(1, 1, Some(&40), None).unwrap()
+        );
+        assert_eq!(None, database.get(&1, &1).unwrap());
+        assert_eq!(Some(&50), database.get(&1, &2).unwrap());
+    }
+
+    #[test]
+    fn update_must_log_resulting_value() {
+        let path = storage_path("update");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.put(1, 2, 20).unwrap();
+
+        let previous = database
+            .update(1, 1, |value| {
+                let previous = *value;
+
+                if let Some(value) = value {
+                    *value += 1;
+                }
+
+                previous
+            })
+            .unwrap();
+
+        database.update(1, 2, |value| *value = None).unwrap();
+        database.update(2, 1, |value| *value = Some(30)).unwrap();
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(Some(10), previous);
+        assert_eq!(Some(&11), database.get(&1, &1).unwrap());
+        assert_eq!(None, database.get(&1, &2).unwrap());
+        assert_eq!(Some(&30), database.get(&2, &1).unwrap());
+    }
+
+    #[test]
+    fn update_must_keep_expiry_and_skip_unchanged_values() {
+        let path = storage_path("update-expiry");
+        let mut database = open(&path);
+
+        database
+            .put_with_ttl(1, 1, 10, Duration::from_secs(3600))
+            .unwrap();
+        database
+            .update(1, 1, |value| *value = value.map(|value| value + 1))
+            .unwrap();
+
+        let expires_at = database.partition_map()[&1].expires_at(&1);
+        let log_length = fs::metadata(path.join("wal.htdb")).unwrap().len();
+
+        database.update(1, 1, |_| ()).unwrap();
+        database.update(2, 1, |value| *value = None).unwrap();
+
+        assert_eq!(true, expires_at.is_some());
+        assert_eq!(
+            log_length,
+            fs::metadata(path.join("wal.htdb")).unwrap().len()
+        );
+        assert_eq!(false, database.contains_partition(&2));
+
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(Some(&11), database.get(&1, &1).unwrap());
+        assert_eq!(expires_at, database.partition_map()[&1].expires_at(&1));
+    }
+
+    #[test]
+    fn merge_must_use_registered_operator() {
+        let path = storage_path("merge");
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let mut database: Database<u32, u32, Vec<u8>> = Database::open(config.clone()).unwrap();
+
+        assert!(matches!(
+            database.merge(1, 1, vec![1]),
+            Err(DatabaseError::MergeError { .. })
+        ));
+
+        database.set_merge_operator(IntegerAdd);
+        database.merge(1, 1, vec![40]).unwrap();
+
+        let merged = database.merge(1, 1, vec![2]).unwrap().cloned();
+
+        database.merge_with(1, 2, b"ab".to_vec(), &Concat).unwrap();
+        database.merge_with(1, 2, b"cd".to_vec(), &Concat).unwrap();
+        drop(database);
+
+        let mut database: Database<u32, u32, Vec<u8>> = Database::open(config).unwrap();
+
+        assert_eq!(Some(42i64.to_le_bytes().to_vec()), merged);
+        assert_eq!(
+            Some(&42i64.to_le_bytes().to_vec()),
+            database.get(&1, &1).unwrap()
+        );
+        assert_eq!(Some(&b"abcd".to_vec()), database.get(&1, &2).unwrap());
+    }
+
+    #[test]
+    fn bulk_load_must_replay_loaded_entries() {
+        let path = storage_path("bulk-load");
+        let mut database = open(&path);
+
+        database.put(1, 50, 0).unwrap();
+
+        let added = database
+            .bulk_load(1, (0..100).map(|index| (index, index)))
+            .unwrap();
+
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(99, added);
+        assert_eq!(100, database.partition_len(&1));
+        assert_eq!(Some(&50), database.get(&1, &50).unwrap());
+        assert_eq!(Some(&99), database.get(&1, &99).unwrap());
+    }
+
+    #[test]
+    fn delete_range_must_replay_deleted_range() {
+        let path = storage_path("delete-range");
+        let mut database = open(&path);
+
+        database
+            .bulk_load(1, (0..100).map(|index| (index, index)))
+            .unwrap();
+        database.put(2, 1, 1).unwrap();
+
+        assert_eq!(90, database.delete_range(&1, 10..).unwrap());
+        assert_eq!(1, database.delete_range(&2, ..).unwrap());
+        assert_eq!(0, database.delete_range(&3, ..).unwrap());
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(10, database.partition_len(&1));
+        assert_eq!(Some(&9), database.get(&1, &9).unwrap());
+        assert_eq!(None, database.get(&1, &10).unwrap());
+        assert_eq!(false, database.contains_partition(&2));
+    }
+
+    #[test]
+    fn max_pages_must_limit_resident_pages_of_all_partitions() {
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(3))
+            .set_storage_path(storage_path("max-pages"));
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        for hash_key in 0..5 {
+            for tree_key in 0..6 {
+                database.put(hash_key, tree_key, tree_key).unwrap();
+            }
+
+            assert_eq!(true, database.counters.residency().pages() <= 3);
+        }
+
+        for hash_key in 0..5 {
+            for tree_key in 0..6 {
+                assert_eq!(Some(&tree_key), database.get(&hash_key, &tree_key).unwrap());
+            }
+        }
+
+        assert_eq!(true, database.counters.residency().pages() <= 3);
+        assert_eq!(30, database.stats().unwrap().entries());
+    }
+
+    #[test]
+    fn memory_budget_must_limit_resident_pages_of_all_partitions() {
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_memory_budget(Some(200))
+            .set_storage_path(storage_path("memory-budget"));
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        for hash_key in 0..10 {
+            for tree_key in 0..6 {
+                database.put(hash_key, tree_key, tree_key).unwrap();
+            }
+
+            assert_eq!(true, database.counters.residency().memory() <= 200);
+        }
+
+        for hash_key in 0..10 {
+            for tree_key in 0..6 {
+                assert_eq!(Some(&tree_key), database.get(&hash_key, &tree_key).unwrap());
+            }
+        }
+
+        let stats = database.stats().unwrap();
+
+        assert_eq!(true, stats.memory() <= 200);
+        assert_eq!(database.counters.residency().memory(), stats.memory());
+    }
+
+    #[test]
+    fn stats_must_describe_pages_and_activity() {
+        let path = storage_path("stats");
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_max_page_size(4);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        for index in 0..10 {
+            database.put(1, index, index).unwrap();
+        }
+
+        database.put(2, 1, 1).unwrap();
+        database.save().unwrap();
+
+        let stats = database.stats().unwrap();
+
+        assert_eq!(2, stats.partitions());
+        assert_eq!(11, stats.entries());
+        assert_eq!(true, stats.pages() >= 4);
+        assert_eq!((stats.pages() - 2) as u64, stats.splits());
+        assert_eq!(0, stats.removed_pages());
+        assert_eq!(0.25, stats.min_page_fill());
+        assert_eq!(11.0 / (stats.pages() * 4) as f64, stats.average_page_fill());
+        assert_eq!(true, stats.max_page_fill() <= 1.0);
+        assert_eq!(88, stats.memory());
+        assert_eq!(true, stats.last_save().is_some());
+        assert_eq!(None, stats.last_load());
+
+        for index in 0..10 {
+            database.delete(&1, &index).unwrap();
+        }
+
+        let stats = database.stats().unwrap();
+
+        assert_eq!(1, stats.pages());
+        assert_eq!(true, stats.removed_pages() > 0);
+    }
+
+    #[test]
+    fn count_must_follow_all_changes() {
+        let path = storage_path("count");
+        let mut database = open(&path);
+
+        database
+            .bulk_load(1, (0..100).map(|index| (index, index)))
+            .unwrap();
+        database.put(2, 1, 1).unwrap();
+        database.put(2, 1, 2).unwrap();
+        database.put(3, 1, 1).unwrap();
+        database.put_with_ttl(3, 2, 2, Duration::ZERO).unwrap();
+
+        assert_eq!(102, database.live_count().unwrap());
+        assert_eq!(103, database.count().unwrap());
+        assert_eq!(None, database.get(&3, &2).unwrap());
+        assert_eq!(102, database.live_count().unwrap());
+        assert_eq!(102, database.count().unwrap());
+
+        database.delete_range(&1, 50..).unwrap();
+        database.delete(&1, &0).unwrap();
+        database.rename_partition(&2, 3).unwrap();
+
+        assert_eq!(50, database.count().unwrap());
+        assert_eq!(49, database.partition_len(&1));
+
+        database.save().unwrap();
+        database.drop_partition(&1).unwrap();
+
+        assert_eq!(1, database.count().unwrap());
+
+        database.put(1, 1, 1).unwrap();
+        drop(database);
+
+        let database = open(&path);
+
+        assert_eq!(2, database.count().unwrap());
+    }
+
+    #[test]
+    fn range_page_must_resume_after_cursor() {
+        let config = Config::default().set_max_page_size(4);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        database
+            .bulk_load(1, (0..20).map(|index| (index, index)))
+            .unwrap();
+
+        let (page, cursor) = database.range_page(&1, 2..=12, 5).unwrap();
+        let cursor = cursor.unwrap();
+
+        assert_eq!(vec![(2, 2), (3, 3), (4, 4), (5, 5), (6, 6)], page);
+        assert_eq!(&6, cursor.last_key());
+
+        // Pages are split and merged between calls.
+        database.delete_range(&1, 3..=5).unwrap();
+        database.put(1, 7, 70).unwrap();
+        database.put(1, 65, 65).unwrap();
+        database.compact().unwrap();
+
+        let (page, cursor) = database.range_page(&1, cursor, 5).unwrap();
+
+        assert_eq!(vec![(7, 70), (8, 8), (9, 9), (10, 10), (11, 11)], page);
+
+        let (page, cursor) = database.range_page(&1, cursor.unwrap(), 5).unwrap();
+
+        assert_eq!(vec![(12, 12)], page);
+        assert_eq!(None, cursor);
+        assert_eq!(None, database.range_page(&1, 12..12, 5).unwrap().1);
+        assert!(matches!(
+            database.range_page(&1, .., 0),
+            Err(DatabaseError::InvalidRange { .. })
+        ));
+    }
+
+    #[test]
+    fn database_must_use_given_hasher() {
+        let path = storage_path("hasher");
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let mut database: Database<u32, u32, u32, TrivialHasherBuilder> =
+            Database::open_with_hasher(config.clone(), TrivialHasherBuilder::default()).unwrap();
+
+        for hash in 0..10 {
+            database.put(hash, hash, hash).unwrap();
+        }
+
+        database.save().unwrap();
+        database.put(10, 10, 10).unwrap();
+        drop(database);
+
+        let mut database: Database<u32, u32, u32, TrivialHasherBuilder> =
+            Database::open_with_hasher(config, TrivialHasherBuilder::default()).unwrap();
+
+        assert_eq!(11, database.count().unwrap());
+        assert_eq!(Some(&5), database.get(&5, &5).unwrap());
+        assert_eq!(Some(&10), database.get(&10, &10).unwrap());
+    }
+
+    #[test]
+    fn load_must_detect_snapshot_compression() {
+        let path = storage_path("compression");
+        let config = Config::default().set_storage_path(&path);
+
+        for compression in [Compression::None, Compression::Zstd(0), Compression::Lz4] {
+            let mut database: Database<u32, u32, u32> =
+                Database::new(config.clone().set_compression(compression));
+
+            database.put(1, 1, compression.compression_id()).unwrap();
+            database.save().unwrap();
+
+            let mut database: Database<u32, u32, u32> = Database::new(config.clone());
+
+            database.load().unwrap();
+
+            assert_eq!(
+                Some(&compression.compression_id()),
+                database.get(&1, &1).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn load_must_decode_snapshot_with_recorded_codec() {
+        let path = storage_path("codec");
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_max_page_size(4);
+        let mut database: Database<String, u32, Vec<u8>, RandomState, MessagePack> =
+            Database::with_codec(config.clone(), RandomState::new(), MessagePack);
+
+        for index in 0..10 {
+            database.put("a".into(), index, vec![index as u8]).unwrap();
+        }
+
+        database.save().unwrap();
+
+        let mut database: Database<String, u32, Vec<u8>, RandomState, MessagePack> =
+            Database::with_codec(config.clone(), RandomState::new(), MessagePack);
+
+        database.load().unwrap();
+
+        assert_eq!(10, database.count().unwrap());
+        assert_eq!(Some(&vec![7]), database.get(&"a".into(), &7).unwrap());
+
+        let mut database: Database<String, u32, Vec<u8>, RandomState, Cbor> =
+            Database::with_codec(config.clone(), RandomState::new(), Cbor);
+
+        database.load().unwrap();
+
+        assert_eq!(10, database.count().unwrap());
+    }
+
+    #[test]
+    fn save_must_truncate_write_ahead_log() {
+        let path = storage_path("truncate");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+
+        assert_eq!(0, fs::metadata(path.join("wal.htdb")).unwrap().len());
+    }
+
+    #[test]
+    fn new_must_reject_unreplayed_write_ahead_log() {
+        let path = storage_path("unreplayed");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        drop(database);
+
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        assert!(matches!(
+            database.put(1, 2, 20),
+            Err(DatabaseError::UnreplayedLog { .. })
+        ));
+        assert!(matches!(
+            database.save(),
+            Err(DatabaseError::UnreplayedLog { .. })
+        ));
+
+        drop(database);
+
+        let mut database = open(&path);
+
+        assert_eq!(Some(&10), database.get(&1, &1).unwrap());
+        assert_eq!(None, database.get(&1, &2).unwrap());
+    }
+
+    #[test]
+    fn save_must_replace_snapshot() {
+        let path = storage_path("replace");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+        database.put(1, 2, 20).unwrap();
+        database.save().unwrap();
+
+        assert_eq!(true, path.join("full.htdb").exists());
+        assert_eq!(false, path.join("full.htdb.tmp").exists());
+    }
+
+    #[test]
+    fn load_must_ignore_temporary_snapshot() {
+        let path = storage_path("temporary");
+        let mut database = open(&path);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+
+        fs::write(path.join("full.htdb.tmp"), b"incomplete").unwrap();
+
+        database.load().unwrap();
+
+        assert_eq!(Some(&10), database.get(&1, &1).unwrap());
+        assert_eq!(false, path.join("full.htdb.tmp").exists());
+    }
+
+    #[test]
+    fn load_must_keep_configured_page_size() {
+        let path = storage_path("page-size");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_storage_path(&path);
+        let mut database = Database::new(config);
+        let mut visiter = PageSizeVisiter::default();
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+        database.load().unwrap();
+
+        for index in 2..10 {
+            database.put(1, index, index).unwrap();
+        }
+
+        database.visit(&mut visiter).unwrap();
+
+        assert_eq!(true, visiter.sizes.iter().all(|&size| size <= 2));
+    }
+
+    #[test]
+    fn load_must_repage_snapshot_with_other_page_size() {
+        let path = storage_path("config");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        for index in 0..10 {
+            database.put(1, index, index).unwrap();
+        }
+
+        database.save().unwrap();
+
+        let config = Config::default()
+            .set_max_page_size(4)
+            .set_min_page_fill(0.5)
+            .set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+        let mut visiter = PageSizeVisiter::default();
+
+        assert_eq!(
+            LoadStatus::Repaged { saved_page_size: 2 },
+            database.load().unwrap()
+        );
+        assert_eq!(10, database.count().unwrap());
+
+        database.visit(&mut visiter).unwrap();
+
+        assert_eq!(
+            true,
+            visiter.sizes.iter().all(|&size| (2..=4).contains(&size))
+        );
+        database.save().unwrap();
+
+        assert_eq!(LoadStatus::Loaded, database.load().unwrap());
+    }
+
+    #[test]
+    fn load_must_read_legacy_snapshot() {
+        let path = storage_path("legacy");
+        let config = Config::default()
+            .set_max_page_size(4)
+            .set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+        // Partitions with pages of range start, range end and tree, as saved before header.
+        let tree: BTreeMap<u32, u32> = (0..10).map(|index| (index, index * 10)).collect();
+        let pages = vec![(0u32, 9u32, tree)];
+        let legacy = HashMap::from([(1u32, (pages,))]);
+
+        fs::create_dir_all(&path).unwrap();
+
+        let file = fs::File::create(path.join("full.htdb")).unwrap();
+        let mut encoder = DeflateEncoder::new(file, DeflateLevel::default());
+
+        bincode::serialize_into(&mut encoder, &legacy).unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(LoadStatus::Loaded, database.load().unwrap());
+        assert_eq!(10, database.count().unwrap());
+        assert_eq!(Some(&90), database.get(&1, &9).unwrap());
+    }
+
+    #[test]
+    fn load_must_reject_foreign_snapshot() {
+        let path = storage_path("foreign");
+        let config = Config::default().set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+        let mut state = 1u32;
+        let random: Vec<u8> = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+
+        fs::create_dir_all(&path).unwrap();
+
+        for bytes in [random, vec![1, 2, 3]] {
+            fs::write(path.join("full.htdb"), bytes).unwrap();
+
+            assert!(matches!(
+                database.load(),
+                Err(DatabaseError::CorruptedSnapshot { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn load_must_reject_other_type_tag() {
+        let path = storage_path("type-tag");
+        let config = Config::default()
+            .set_type_tag(Some(1))
+            .set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+
+        let config = Config::default()
+            .set_type_tag(Some(2))
+            .set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        assert!(matches!(
+            database.load(),
+            Err(DatabaseError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn load_must_reject_snapshot_of_other_types() {
+        let path = storage_path("other-types");
+        let config = Config::default().set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config.clone());
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+
+        let mut database: Database<String, String, String> = Database::new(config);
+
+        assert!(matches!(
+            database.load(),
+            Err(DatabaseError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn failed_load_must_keep_database_unchanged() {
+        let path = storage_path("failed-load");
+        let config = Config::default().set_storage_path(&path);
+        let mut database: Database<u32, u32, u32> = Database::new(config);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+        database.put(1, 2, 20).unwrap();
+        fs::write(path.join("full.htdb"), b"broken").unwrap();
+
+        assert_eq!(true, database.load().is_err());
+        assert_eq!(2, database.count().unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
     }
 }
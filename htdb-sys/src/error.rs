@@ -4,6 +4,8 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
 use std::io::Error as IoError;
+use std::path::Path;
+use std::sync::PoisonError;
 
 #[derive(Debug)]
 pub enum DatabaseError {
@@ -12,6 +14,14 @@ pub enum DatabaseError {
     OpenFileError { message: String },
     SerializeError { message: String },
     InvalidRange { message: String },
+    CorruptedSnapshot { message: String },
+    VersionMismatch { message: String },
+    TypeMismatch { message: String },
+    CodecMismatch { message: String },
+    LockError { message: String },
+    MergeError { message: String },
+    RevertError { message: String },
+    UnreplayedLog { message: String },
 }
 
 impl DatabaseError {
@@ -39,6 +49,16 @@ impl DatabaseError {
         }
     }
 
+    /// Wraps error reported by snapshot codec other than bincode.
+    pub fn codec_error<E>(error: E) -> DatabaseError
+    where
+        E: Display,
+    {
+        DatabaseError::SerializeError {
+            message: format!("{}", error),
+        }
+    }
+
     pub fn invalid_range<S>(message: S) -> DatabaseError
     where
         S: Into<String>,
@@ -47,6 +67,78 @@ impl DatabaseError {
             message: message.into(),
         }
     }
+
+    pub fn corrupted_snapshot<S>(message: S) -> DatabaseError
+    where
+        S: Into<String>,
+    {
+        DatabaseError::CorruptedSnapshot {
+            message: message.into(),
+        }
+    }
+
+    pub fn version_mismatch(expected: u32, found: u32) -> DatabaseError {
+        DatabaseError::VersionMismatch {
+            message: format!(
+                "Unsupported snapshot format version {}, expected {}",
+                found, expected
+            ),
+        }
+    }
+
+    pub fn codec_mismatch(saved_codec: u32, codec: u32) -> DatabaseError {
+        DatabaseError::CodecMismatch {
+            message: format!(
+                "Snapshot was encoded with codec {}, but database uses codec {}",
+                saved_codec, codec
+            ),
+        }
+    }
+
+    pub fn lock_error<T>(error: PoisonError<T>) -> DatabaseError {
+        DatabaseError::LockError {
+            message: format!("{}", error),
+        }
+    }
+
+    pub fn type_mismatch<S>(message: S) -> DatabaseError
+    where
+        S: Into<String>,
+    {
+        DatabaseError::TypeMismatch {
+            message: message.into(),
+        }
+    }
+
+    pub fn merge_error<S>(message: S) -> DatabaseError
+    where
+        S: Into<String>,
+    {
+        DatabaseError::MergeError {
+            message: message.into(),
+        }
+    }
+
+    /// Failed transaction could not be reverted, database may keep part of its changes.
+    pub fn revert_error(error: DatabaseError, revert_error: DatabaseError) -> DatabaseError {
+        DatabaseError::RevertError {
+            message: format!(
+                "Transaction failed: {}, revert failed: {}",
+                error, revert_error
+            ),
+        }
+    }
+
+    /// Database created without replaying its write-ahead log is about to write to the log, so
+    /// records left by previous run would be lost.
+    pub fn unreplayed_log(path: &Path) -> DatabaseError {
+        DatabaseError::UnreplayedLog {
+            message: format!(
+                "Write-ahead log {} contains records which were not replayed, database must be opened with open",
+                path.display()
+            ),
+        }
+    }
 }
 
 impl Error for DatabaseError {}
@@ -59,6 +151,14 @@ impl Display for DatabaseError {
             DatabaseError::OpenFileError { message } => write!(f, "{}", message),
             DatabaseError::SerializeError { message } => write!(f, "{}", message),
             DatabaseError::InvalidRange { message } => write!(f, "{}", message),
+            DatabaseError::CorruptedSnapshot { message } => write!(f, "{}", message),
+            DatabaseError::VersionMismatch { message } => write!(f, "{}", message),
+            DatabaseError::TypeMismatch { message } => write!(f, "{}", message),
+            DatabaseError::CodecMismatch { message } => write!(f, "{}", message),
+            DatabaseError::LockError { message } => write!(f, "{}", message),
+            DatabaseError::MergeError { message } => write!(f, "{}", message),
+            DatabaseError::RevertError { message } => write!(f, "{}", message),
+            DatabaseError::UnreplayedLog { message } => write!(f, "{}", message),
         }
     }
 }
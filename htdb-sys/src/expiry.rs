@@ -0,0 +1,17 @@
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Returns current time in milliseconds since UNIX epoch. Expiry times are stored as absolute
+/// timestamps, so they stay valid after save and load.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Returns time when entry with given time-to-live expires.
+pub fn expires_at(ttl: Duration) -> u64 {
+    now().saturating_add(ttl.as_millis() as u64)
+}
@@ -1,6 +1,9 @@
 use std::hash::BuildHasherDefault;
 use std::hash::Hasher;
 
+/// Hasher which folds written bytes into 64-bit value with XOR. It is fast for integer
+/// partition keys, but byte and string keys collide easily, so it must not be used with keys
+/// from untrusted sources.
 #[derive(Default)]
 pub struct TrivialHasher {
     hash: u64,
@@ -42,7 +45,7 @@ impl Hasher for TrivialHasher {
     }
 
     fn write_u64(&mut self, i: u64) {
-        self.hash ^= i as u64;
+        self.hash ^= i;
     }
 
     fn write_u128(&mut self, i: u128) {
@@ -78,4 +81,5 @@ impl Hasher for TrivialHasher {
     }
 }
 
+/// Builds `TrivialHasher`, pass it to `Database::with_hasher`.
 pub type TrivialHasherBuilder = BuildHasherDefault<TrivialHasher>;
@@ -1,12 +1,47 @@
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
+mod codec;
+mod compression;
 mod config;
+mod cursor;
 mod database;
 mod error;
+mod expiry;
 mod hasher;
+mod merge;
 mod pages;
+mod prefix;
+mod shared;
+mod size;
+mod snapshot;
+mod stats;
+mod transaction;
 mod visiter;
+mod wal;
 
+pub use crate::codec::Bincode;
+pub use crate::codec::BuiltinCodec;
+pub use crate::codec::Cbor;
+pub use crate::codec::Codec;
+pub use crate::codec::MessagePack;
+pub use crate::compression::Compression;
 pub use crate::config::Config;
+pub use crate::config::LoadStatus;
+pub use crate::cursor::Cursor;
+pub use crate::cursor::RangePage;
 pub use crate::database::Database;
 pub use crate::error::DatabaseError;
+pub use crate::hasher::TrivialHasher;
+pub use crate::hasher::TrivialHasherBuilder;
+pub use crate::merge::Concat;
+pub use crate::merge::IntegerAdd;
+pub use crate::merge::MergeOperator;
+pub use crate::prefix::PrefixKey;
+pub use crate::shared::SharedDatabase;
+pub use crate::size::EstimateSize;
+pub use crate::size::SerializedSize;
+pub use crate::stats::Stats;
+pub use crate::transaction::SharedTransaction;
+pub use crate::transaction::Transaction;
 pub use crate::visiter::HashTreeVisiter;
 pub use crate::visiter::PrintVisiter;
@@ -0,0 +1,136 @@
+use crate::DatabaseError;
+use std::fmt::Debug;
+
+/// Combines stored value with operand passed to `Database::merge`. Value is `None` when key is
+/// absent. Value left in the option after the call is stored, `None` removes the key.
+pub trait MergeOperator<V>: Debug + Send + Sync {
+    fn merge(&self, value: &mut Option<V>, operand: V) -> Result<(), DatabaseError>;
+}
+
+/// Adds operand to value as signed 64-bit integers, wrapping on overflow. Absent value counts as
+/// zero. Byte values hold little endian integers of up to 8 bytes and the sum is always stored
+/// in 8 bytes, string values hold decimal integers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntegerAdd;
+
+/// Appends operand to the end of value. Absent value counts as empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Concat;
+
+impl MergeOperator<Vec<u8>> for IntegerAdd {
+    fn merge(&self, value: &mut Option<Vec<u8>>, operand: Vec<u8>) -> Result<(), DatabaseError> {
+        let current = match value {
+            Some(bytes) => decode_integer(bytes)?,
+            None => 0,
+        };
+        let sum = current.wrapping_add(decode_integer(&operand)?);
+
+        *value = Some(sum.to_le_bytes().to_vec());
+
+        Ok(())
+    }
+}
+
+impl MergeOperator<String> for IntegerAdd {
+    fn merge(&self, value: &mut Option<String>, operand: String) -> Result<(), DatabaseError> {
+        let current = match value {
+            Some(text) => parse_integer(text)?,
+            None => 0,
+        };
+        let sum = current.wrapping_add(parse_integer(&operand)?);
+
+        *value = Some(sum.to_string());
+
+        Ok(())
+    }
+}
+
+impl MergeOperator<Vec<u8>> for Concat {
+    fn merge(&self, value: &mut Option<Vec<u8>>, operand: Vec<u8>) -> Result<(), DatabaseError> {
+        value.get_or_insert_with(Vec::new).extend(operand);
+
+        Ok(())
+    }
+}
+
+impl MergeOperator<String> for Concat {
+    fn merge(&self, value: &mut Option<String>, operand: String) -> Result<(), DatabaseError> {
+        value.get_or_insert_with(String::new).push_str(&operand);
+
+        Ok(())
+    }
+}
+
+/// Reads little endian integer, shorter values are sign extended.
+fn decode_integer(bytes: &[u8]) -> Result<i64, DatabaseError> {
+    if bytes.len() > 8 {
+        return Err(DatabaseError::merge_error(format!(
+            "Integer value must not be longer than 8 bytes, found {} bytes",
+            bytes.len()
+        )));
+    }
+
+    let fill = match bytes.last() {
+        Some(last) if *last >= 0x80 => 0xFF,
+        _ => 0,
+    };
+    let mut buffer = [fill; 8];
+
+    buffer[..bytes.len()].copy_from_slice(bytes);
+
+    Ok(i64::from_le_bytes(buffer))
+}
+
+fn parse_integer(text: &str) -> Result<i64, DatabaseError> {
+    text.trim()
+        .parse()
+        .map_err(|_| DatabaseError::merge_error(format!("Value `{}` is not an integer", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::Concat;
+    use crate::merge::IntegerAdd;
+    use crate::merge::MergeOperator;
+
+    #[test]
+    fn integer_add_must_sum_little_endian_bytes() {
+        let mut value = None;
+
+        IntegerAdd.merge(&mut value, vec![5]).unwrap();
+        IntegerAdd
+            .merge(&mut value, (-7i64).to_le_bytes().to_vec())
+            .unwrap();
+        IntegerAdd.merge(&mut value, vec![0x00, 0x01]).unwrap();
+
+        assert_eq!(Some(254i64.to_le_bytes().to_vec()), value);
+        assert_eq!(true, IntegerAdd.merge(&mut value, vec![0; 9]).is_err());
+    }
+
+    #[test]
+    fn integer_add_must_sum_decimal_strings() {
+        let mut value = Some("40".to_string());
+
+        IntegerAdd.merge(&mut value, "2".to_string()).unwrap();
+
+        assert_eq!(Some("42".to_string()), value);
+        assert_eq!(
+            true,
+            IntegerAdd.merge(&mut value, "two".to_string()).is_err()
+        );
+        assert_eq!(Some("42".to_string()), value);
+    }
+
+    #[test]
+    fn concat_must_append_operand() {
+        let mut bytes = None;
+        let mut text = Some("ab".to_string());
+
+        Concat.merge(&mut bytes, vec![1, 2]).unwrap();
+        Concat.merge(&mut bytes, vec![3]).unwrap();
+        Concat.merge(&mut text, "cd".to_string()).unwrap();
+
+        assert_eq!(Some(vec![1, 2, 3]), bytes);
+        assert_eq!(Some("abcd".to_string()), text);
+    }
+}
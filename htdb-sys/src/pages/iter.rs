@@ -0,0 +1,122 @@
+use super::is_expired;
+use super::is_inverted;
+use super::Pages;
+use crate::DatabaseError;
+use crate::EstimateSize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::ops::Bound;
+use std::ops::Range;
+
+/// Iterator over entries of pages within bounds in both directions. Swapped out page is loaded
+/// when iteration reaches it, so iterator dropped early does not load the rest of the range.
+/// Entries are cloned out of pages, so only the page under the cursor is kept resident and
+/// passed pages are swapped out when they exceed the limits.
+pub struct RangeIter<'a, K, V>
+where
+    K: Ord,
+{
+    pages: &'a mut Pages<K, V>,
+    /// Bounds of entries not returned from either end yet.
+    bounds: (Bound<K>, Bound<K>),
+    /// Pages which may contain entries within bounds.
+    span: Range<usize>,
+    now: u64,
+}
+
+impl<'a, K, V> RangeIter<'a, K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+{
+    pub fn new(
+        pages: &'a mut Pages<K, V>,
+        bounds: (Bound<K>, Bound<K>),
+        span: Range<usize>,
+        now: u64,
+    ) -> RangeIter<'a, K, V> {
+        RangeIter {
+            pages,
+            bounds,
+            span,
+            now,
+        }
+    }
+
+    /// Loads page at `index` and returns its first or last entry within bounds, which is not
+    /// expired.
+    fn entry(&mut self, index: usize, back: bool) -> Result<Option<(K, V)>, DatabaseError> {
+        if is_inverted(&self.bounds) {
+            return Ok(None);
+        }
+
+        self.pages.prepare(index..index + 1)?;
+
+        let expiry = &self.pages.expiry;
+        let now = self.now;
+        let mut entries = self.pages.pages[index]
+            .range_iter(self.bounds.clone())
+            .filter(|(key, _)| !is_expired(expiry, key, now));
+        let entry = if back {
+            entries.next_back()
+        } else {
+            entries.next()
+        };
+
+        Ok(entry.map(|(key, value)| (key.clone(), value.clone())))
+    }
+
+    /// Stops iteration after failed page load.
+    fn fail(&mut self, error: DatabaseError) -> Option<Result<(K, V), DatabaseError>> {
+        self.span = 0..0;
+
+        Some(Err(error))
+    }
+}
+
+impl<'a, K, V> Iterator for RangeIter<'a, K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+{
+    type Item = Result<(K, V), DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.span.is_empty() {
+            match self.entry(self.span.start, false) {
+                Ok(Some((key, value))) => {
+                    self.bounds.0 = Bound::Excluded(key.clone());
+
+                    return Some(Ok((key, value)));
+                }
+                Ok(None) => self.span.start += 1,
+                Err(error) => return self.fail(error),
+            }
+        }
+
+        None
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for RangeIter<'a, K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while !self.span.is_empty() {
+            match self.entry(self.span.end - 1, true) {
+                Ok(Some((key, value))) => {
+                    self.bounds.1 = Bound::Excluded(key.clone());
+
+                    return Some(Ok((key, value)));
+                }
+                Ok(None) => self.span.end -= 1,
+                Err(error) => return self.fail(error),
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,176 @@
+use super::page::Page;
+use super::Residency;
+use crate::EstimateSize;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Resident pages of partition in order of their last access. Page touched through shared
+/// reference keeps its position and is moved to the tail when it reaches the head, so pages are
+/// touched without mutable access and eviction never scans all pages.
+#[derive(Debug)]
+pub struct Lru<K> {
+    clock: AtomicU64,
+    /// Range start of resident page by its access time. Entries of pages swapped out, removed or
+    /// changed since are dropped when they reach the head.
+    entries: BTreeMap<u64, K>,
+    /// Number of resident pages of partition.
+    resident: usize,
+    /// Approximate size of resident pages of partition in bytes.
+    memory: usize,
+    /// Resident pages of all partitions of database, shared between them.
+    residency: Arc<Residency>,
+}
+
+impl<K> Lru<K>
+where
+    K: Ord + Clone + Debug + EstimateSize,
+{
+    pub fn new(residency: Arc<Residency>) -> Lru<K> {
+        Lru {
+            clock: AtomicU64::new(0),
+            entries: BTreeMap::new(),
+            resident: 0,
+            memory: 0,
+            residency,
+        }
+    }
+
+    pub fn residency(&self) -> &Residency {
+        &self.residency
+    }
+
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Moves resident pages of partition from the current residency to `residency`.
+    pub fn set_residency(&mut self, residency: Arc<Residency>) {
+        self.residency.release(self.resident, self.memory);
+        residency.admit(self.resident, self.memory);
+        self.residency = residency;
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Marks resident page as recently used.
+    pub fn touch<V: Debug + EstimateSize>(&self, page: &Page<K, V>) {
+        page.set_access(self.tick());
+    }
+
+    /// Adds page which was created or loaded from disk.
+    pub fn admit<V: Debug + EstimateSize>(&mut self, page: &Page<K, V>) {
+        let access = self.tick();
+
+        page.set_access(access);
+        self.entries.insert(access, page.range_start().clone());
+        self.resident += 1;
+        self.memory += page.bytes();
+        self.residency.admit(1, page.bytes());
+    }
+
+    /// Removes page of `bytes` size which was swapped out or dropped while resident.
+    pub fn release(&mut self, bytes: usize) {
+        self.resident -= 1;
+        self.memory -= bytes;
+        self.residency.release(1, bytes);
+    }
+
+    /// Records changed size of resident page.
+    pub fn resize(&mut self, before: usize, after: usize) {
+        self.memory = self.memory - before + after;
+        self.residency.release(0, before);
+        self.residency.admit(0, after);
+    }
+
+    /// Records new range start of resident page.
+    pub fn rekey<V: Debug + EstimateSize>(&mut self, page: &Page<K, V>) {
+        self.entries
+            .insert(page.access(), page.range_start().clone());
+    }
+
+    /// Replaces entries with resident `pages` keeping their order, used after pages are
+    /// deserialized and to drop stale entries.
+    pub fn rebuild<V: Debug + EstimateSize>(&mut self, pages: &[Page<K, V>]) {
+        let mut resident: Vec<_> = pages.iter().filter(|page| page.is_resident()).collect();
+
+        let memory = resident.iter().map(|page| page.bytes()).sum();
+
+        resident.sort_by_key(|page| page.access());
+        self.residency.release(self.resident, self.memory);
+        self.residency.admit(resident.len(), memory);
+        self.resident = resident.len();
+        self.memory = memory;
+        self.entries.clear();
+
+        for page in resident {
+            let access = self.tick();
+
+            page.set_access(access);
+            self.entries.insert(access, page.range_start().clone());
+        }
+    }
+
+    /// Drops stale entries left by pages removed while resident when they outnumber resident
+    /// pages.
+    pub fn compact<V: Debug + EstimateSize>(&mut self, pages: &[Page<K, V>]) {
+        if self.entries.len() > 2 * self.resident + 16 {
+            self.rebuild(pages);
+        }
+    }
+
+    /// Returns index of the least recently used resident page outside of `keep`.
+    pub fn victim<V: Debug + EstimateSize>(
+        &mut self,
+        pages: &[Page<K, V>],
+        keep: &Range<usize>,
+    ) -> Option<usize> {
+        let mut kept = Vec::new();
+        let mut result = None;
+
+        while let Some((access, key)) = self.entries.pop_first() {
+            let index = pages.partition_point(|page| page.range_start() < &key);
+            let page = match pages.get(index) {
+                Some(page) if page.range_start() == &key && page.is_resident() => page,
+                _ => continue,
+            };
+
+            if page.access() != access {
+                self.entries.insert(page.access(), key);
+            } else if keep.contains(&index) {
+                kept.push((access, key));
+            } else {
+                result = Some(index);
+
+                break;
+            }
+        }
+
+        self.entries.extend(kept);
+
+        result
+    }
+}
+
+impl<K> Default for Lru<K> {
+    fn default() -> Lru<K> {
+        Lru {
+            clock: AtomicU64::new(0),
+            entries: BTreeMap::new(),
+            resident: 0,
+            memory: 0,
+            residency: Arc::default(),
+        }
+    }
+}
+
+impl<K> Drop for Lru<K> {
+    fn drop(&mut self) {
+        self.residency.release(self.resident, self.memory);
+    }
+}
@@ -1,203 +1,1142 @@
+mod iter;
+mod lru;
 mod page;
+mod swap;
 
+pub use self::iter::RangeIter;
+use self::lru::Lru;
 pub use self::page::Page;
 use crate::config::Config;
+use crate::expiry;
+use crate::size;
 use crate::visiter::TreeVisiter;
+use crate::DatabaseError;
+use crate::EstimateSize;
+use crate::PrefixKey;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::mem;
+use std::ops::Bound;
+use std::ops::Range;
+use std::ops::RangeBounds;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Counters shared by all partitions of database.
+#[derive(Debug, Default)]
+pub struct Counters {
+    entries: AtomicUsize,
+    splits: AtomicU64,
+    removed_pages: AtomicU64,
+    residency: Arc<Residency>,
+}
+
+/// Number and approximate size of resident pages of all partitions of database, kept up to date
+/// by pages, so `Config::max_pages` and `Config::memory_budget` limit the whole database without
+/// measuring its pages. Shards of `SharedDatabase` share single residency.
+#[derive(Debug, Default)]
+pub struct Residency {
+    pages: AtomicUsize,
+    memory: AtomicUsize,
+}
+
+impl Counters {
+    /// Creates counters which report resident pages to `residency`.
+    pub fn with_residency(residency: Arc<Residency>) -> Counters {
+        Counters {
+            residency,
+            ..Counters::default()
+        }
+    }
+
+    pub fn residency(&self) -> &Residency {
+        &self.residency
+    }
+
+    /// Returns number of entries in all partitions.
+    pub fn entries(&self) -> usize {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    /// Returns number of page splits since database was created.
+    pub fn splits(&self) -> u64 {
+        self.splits.load(Ordering::Relaxed)
+    }
+
+    /// Returns number of pages removed because they were emptied or merged into adjacent page.
+    pub fn removed_pages(&self) -> u64 {
+        self.removed_pages.load(Ordering::Relaxed)
+    }
+}
+
+impl Residency {
+    pub fn pages(&self) -> usize {
+        self.pages.load(Ordering::Relaxed)
+    }
+
+    /// Returns approximate size of resident pages in bytes.
+    pub fn memory(&self) -> usize {
+        self.memory.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if resident pages exceed `Config::max_pages` or `Config::memory_budget`.
+    pub fn exceeds(&self, config: &Config) -> bool {
+        config
+            .max_pages()
+            .is_some_and(|max_pages| self.pages() > max_pages.max(1))
+            || config
+                .memory_budget()
+                .is_some_and(|memory_budget| self.memory() > memory_budget)
+    }
+
+    fn admit(&self, pages: usize, bytes: usize) {
+        self.pages.fetch_add(pages, Ordering::Relaxed);
+        self.memory.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn release(&self, pages: usize, bytes: usize) {
+        self.pages.fetch_sub(pages, Ordering::Relaxed);
+        self.memory.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned",
+    deserialize = "K: DeserializeOwned, V: DeserializeOwned"
+))]
 pub struct Pages<K, V>
 where
     K: Ord,
 {
     #[serde(skip)]
-    config: Rc<Config>,
+    config: Arc<Config>,
+    /// Resident pages in order of access.
+    #[serde(skip)]
+    lru: Lru<K>,
+    /// Number of entries in all pages.
+    #[serde(skip)]
+    size: usize,
+    /// Counters of all partitions of database, shared between them.
+    #[serde(skip)]
+    counters: Arc<Counters>,
+    pages: Vec<Page<K, V>>,
+    /// Expiry times of entries with time-to-live in milliseconds since UNIX epoch. Kept outside
+    /// of pages, so expiry can be checked without loading swapped out page.
+    expiry: BTreeMap<K, u64>,
+}
+
+/// Pages as saved by versions before snapshot header, they did not store expiry of entries.
+#[derive(Debug, Deserialize)]
+#[serde(bound(deserialize = "K: DeserializeOwned, V: DeserializeOwned"))]
+pub struct LegacyPages<K, V>
+where
+    K: Ord,
+{
     pages: Vec<Page<K, V>>,
 }
 
 impl<K, V> Pages<K, V>
 where
-    K: Ord + Clone + Debug,
-    V: Debug,
+    K: Ord + Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Serialize + DeserializeOwned + Debug + EstimateSize,
 {
-    pub fn new(config: Rc<Config>) -> Pages<K, V> {
+    #[cfg(test)]
+    pub fn new(config: Arc<Config>) -> Pages<K, V> {
+        Pages::with_counters(config, Arc::default())
+    }
+
+    /// Creates empty pages which report number of their entries, splits and removed pages to
+    /// `counters`.
+    pub fn with_counters(config: Arc<Config>, counters: Arc<Counters>) -> Pages<K, V> {
         Pages {
+            lru: Lru::new(counters.residency.clone()),
             config,
+            size: 0,
+            counters,
             pages: Vec::new(),
+            expiry: BTreeMap::new(),
         }
     }
 
-    pub fn get(&self, key: &K) -> Option<&V> {
-        if self.pages.is_empty() {
-            return None;
+    /// Replaces configuration, it is not stored with pages and must be set after deserialization.
+    /// Creates pages from legacy snapshot, entries must be counted by `recount`.
+    pub fn from_legacy(config: Arc<Config>, legacy: LegacyPages<K, V>) -> Pages<K, V> {
+        let mut pages = Pages::with_counters(config, Arc::default());
+
+        pages.pages = legacy.pages;
+        pages
+    }
+
+    pub fn set_config(&mut self, config: Arc<Config>) {
+        self.config = config;
+    }
+
+    /// Moves number of entries from the current counters to `counters`.
+    pub fn set_counters(&mut self, counters: Arc<Counters>) {
+        self.counters
+            .entries
+            .fetch_sub(self.size, Ordering::Relaxed);
+        counters.entries.fetch_add(self.size, Ordering::Relaxed);
+        self.lru.set_residency(counters.residency.clone());
+        self.counters = counters;
+    }
+
+    /// Counts entries of all pages and estimates their size, neither is stored with pages and
+    /// must be counted after deserialization.
+    pub fn recount(&mut self) {
+        let size = self.pages.iter().map(|page| page.size()).sum();
+
+        for page in &mut self.pages {
+            page.measure();
         }
 
-        match self.pages.partition_point(|page| page.range_start() <= key) {
-            0 => None,
-            index => self.pages[index - 1].get(key),
+        self.lru.rebuild(&self.pages);
+        self.shrink(self.size);
+        self.grow(size);
+    }
+
+    pub fn get(&mut self, key: &K) -> Result<Option<&V>, DatabaseError> {
+        if self.is_expired(key, expiry::now()) {
+            self.remove(key)?;
+
+            return Ok(None);
         }
+
+        self.prepare(self.key_span(key))?;
+
+        Ok(self.try_get(key).flatten())
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> bool {
-        if self.pages.is_empty() {
-            let page = Page::from_key_value(key, value);
+    /// Same as `get` but returns `None` instead of loading swapped out page.
+    pub fn try_get(&self, key: &K) -> Option<Option<&V>> {
+        let span = self.key_span(key);
 
-            self.pages.push(page);
+        if !self.touch_resident(span.clone()) {
+            return None;
+        }
 
-            return true;
+        if self.is_expired(key, expiry::now()) {
+            return Some(None);
         }
 
-        match self
-            .pages
-            .partition_point(|page| page.range_start() <= &key)
-        {
-            0 => {
-                let page = Page::from_key_value(key, value);
+        Some(
+            span.map(|index| &self.pages[index])
+                .find_map(|page| page.get(key)),
+        )
+    }
 
-                self.pages.insert(0, page);
+    pub fn insert(&mut self, key: K, value: V) -> Result<bool, DatabaseError> {
+        self.replace(key, value).map(|previous| previous.is_none())
+    }
 
-                true
+    /// Inserts value which becomes invisible at `expires_at` milliseconds since UNIX epoch.
+    pub fn insert_with_expiry(
+        &mut self,
+        key: K,
+        value: V,
+        expires_at: u64,
+    ) -> Result<bool, DatabaseError> {
+        let result = self.insert(key.clone(), value)?;
+
+        self.expiry.insert(key, expires_at);
+
+        Ok(result)
+    }
+
+    /// Same as `insert` but returns previous value stored by the key.
+    pub fn replace(&mut self, key: K, value: V) -> Result<Option<V>, DatabaseError> {
+        self.expiry.remove(&key);
+        self.update(key, |current| current.replace(value))
+    }
+
+    /// Passes current value to `f` and stores value left in the option after the call, so value
+    /// can be changed in place, inserted or removed. Page is split or merged in the same way as
+    /// after `insert` or `remove`.
+    pub fn update<F, T>(&mut self, key: K, f: F) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(&mut Option<V>) -> T,
+    {
+        if self.is_expired(&key, expiry::now()) {
+            self.remove(&key)?;
+        }
+
+        let index = match self.page_index(&key) {
+            Some(index) => index,
+            None => {
+                let mut value = None;
+                let result = f(&mut value);
+
+                if let Some(value) = value {
+                    self.pages.insert(0, Page::from_key_value(key, value));
+                    self.admit(0);
+                    self.grow(1);
+                    self.touch(0)?;
+                }
+
+                return Ok(result);
             }
-            index => {
-                let page = &mut self.pages[index - 1];
+        };
+
+        self.touch(index)?;
+
+        let page = &mut self.pages[index];
+        let bytes = page.bytes();
+        let mut value = page.remove(&key);
+        let existed = value.is_some();
+        let result = f(&mut value);
 
+        match value {
+            Some(value) => {
                 if page.range_end() < &key {
                     page.set_range_end(key.clone());
                 }
 
-                let result = page.insert(key, value);
+                page.insert(key, value);
+
+                let next = page.is_overfilled(&self.config).then(|| page.split());
+
+                self.lru.resize(bytes, page.bytes());
+
+                if let Some(next) = next {
+                    self.counters.splits.fetch_add(1, Ordering::Relaxed);
+                    self.pages.insert(index + 1, next);
+                    self.admit(index + 1);
+                    self.touch(index + 1)?;
+                }
+
+                if !existed {
+                    self.grow(1);
+                }
+            }
+            None if existed => {
+                let size = page.size();
+
+                self.lru.resize(bytes, page.bytes());
+                self.expiry.remove(&key);
+                self.shrink(1);
+
+                if size == 0 {
+                    self.remove_page(index);
+                } else if size < self.min_page_size() {
+                    self.merge_underfilled(index)?;
+                }
+            }
+            None => {}
+        }
 
-                if page.size() > self.config.max_page_size() {
-                    let next = page.split();
+        Ok(result)
+    }
 
-                    self.pages.insert(index, next);
+    /// Inserts entries in bulk. Entries following all existing keys in ascending order are
+    /// collected into full pages appended to the end, other entries are inserted one by one and
+    /// under-filled pages left by them are merged at the end. Returns number of added keys.
+    pub fn bulk_load<I>(&mut self, entries: I) -> Result<usize, DatabaseError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let max_page_size = self.config.max_page_size().max(1);
+        let mut buffer = BTreeMap::new();
+        let mut buffer_bytes = 0;
+        let mut added = 0;
+        let mut out_of_order = false;
+
+        for (key, value) in entries {
+            let last = match buffer.keys().next_back() {
+                Some(last) => Some(last),
+                None => self.pages.last().map(|page| page.range_end()),
+            };
+
+            if last.is_none_or(|last| last < &key) {
+                buffer_bytes += size::entry_size(&key, &value);
+                buffer.insert(key, value);
+                added += 1;
+
+                if buffer.len() == max_page_size
+                    || self
+                        .config
+                        .max_page_bytes()
+                        .is_some_and(|max| buffer_bytes >= max)
+                {
+                    self.append_page(mem::take(&mut buffer))?;
+                    buffer_bytes = 0;
                 }
+            } else {
+                if !buffer.is_empty() {
+                    self.append_page(mem::take(&mut buffer))?;
+                    buffer_bytes = 0;
+                }
+
+                out_of_order = true;
 
-                result
+                if self.insert(key, value)? {
+                    added += 1;
+                }
             }
         }
+
+        if !buffer.is_empty() {
+            self.append_page(buffer)?;
+        }
+
+        if out_of_order {
+            self.compact()?;
+        }
+
+        Ok(added)
+    }
+
+    pub fn contains(&mut self, key: &K) -> Result<bool, DatabaseError> {
+        if self.is_expired(key, expiry::now()) {
+            self.remove(key)?;
+
+            return Ok(false);
+        }
+
+        self.prepare(self.key_span(key))?;
+
+        Ok(self.try_contains(key).unwrap_or(false))
+    }
+
+    /// Same as `contains` but returns `None` instead of loading swapped out page.
+    pub fn try_contains(&self, key: &K) -> Option<bool> {
+        let mut span = self.key_span(key);
+
+        if !self.touch_resident(span.clone()) {
+            return None;
+        }
+
+        if self.is_expired(key, expiry::now()) {
+            return Some(false);
+        }
+
+        Some(span.any(|index| self.pages[index].contains(key)))
     }
 
-    pub fn contains(&self, key: &K) -> bool {
+    /// Removes value and returns it.
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, DatabaseError> {
+        self.expiry.remove(key);
+
         if self.pages.is_empty() {
-            return false;
+            return Ok(None);
+        }
+
+        match self.pages.partition_point(|page| page.range_start() <= key) {
+            0 => Ok(None),
+            index => {
+                let index = index - 1;
+
+                self.touch(index)?;
+
+                let page = &mut self.pages[index];
+                let bytes = page.bytes();
+                let result = page.remove(key);
+
+                if result.is_some() {
+                    let size = page.size();
+
+                    self.lru.resize(bytes, page.bytes());
+
+                    self.shrink(1);
+
+                    if size == 0 {
+                        self.remove_page(index);
+                    } else if size < self.min_page_size() {
+                        self.merge_underfilled(index)?;
+                    }
+                }
+
+                Ok(result)
+            }
+        }
+    }
+
+    pub fn range<F>(
+        &mut self,
+        key_first: &K,
+        key_last: &K,
+        mut callback: F,
+    ) -> Result<(), DatabaseError>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let now = expiry::now();
+
+        for index in self.range_span(key_first, key_last) {
+            self.prepare(index..index + 1)?;
+
+            let expiry_map = &self.expiry;
+
+            if !self.pages[index].range(key_first, key_last, |key, value| {
+                is_expired(expiry_map, key, now) || callback(key, value)
+            }) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same as `range` but returns `None` without calling `callback` if any page in range is
+    /// swapped out.
+    pub fn try_range<F>(&self, key_first: &K, key_last: &K, mut callback: F) -> Option<()>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let span = self.range_span(key_first, key_last);
+
+        if !self.touch_resident(span.clone()) {
+            return None;
+        }
+
+        let now = expiry::now();
+
+        for index in span {
+            if !self.pages[index].range(key_first, key_last, |key, value| {
+                is_expired(&self.expiry, key, now) || callback(key, value)
+            }) {
+                break;
+            }
+        }
+
+        Some(())
+    }
+
+    /// Returns iterator over entries in `range` in both directions. Swapped out pages are loaded
+    /// when iteration reaches them, see `RangeIter`. Inverted range is treated as empty.
+    pub fn range_iter<R>(&mut self, range: R) -> RangeIter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+        V: Clone,
+    {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        let span = if is_inverted(&bounds) {
+            0..0
+        } else {
+            self.bounds_span(&bounds)
+        };
+
+        RangeIter::new(self, bounds, span, expiry::now())
+    }
+
+    /// Same as `range_iter` but never loads pages, iterator returns `None` when it reaches swapped
+    /// out page.
+    pub fn try_range_iter<R>(&self, range: R) -> impl Iterator<Item = Option<(&K, &V)>> + '_
+    where
+        R: RangeBounds<K>,
+    {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        let span = if is_inverted(&bounds) {
+            0..0
+        } else {
+            self.bounds_span(&bounds)
+        };
+        let now = expiry::now();
+
+        self.pages[span]
+            .iter()
+            .flat_map(move |page| {
+                let resident = page.is_resident();
+
+                if resident {
+                    self.lru.touch(page);
+                }
+
+                let entries = resident.then(|| page.range_iter(bounds.clone()));
+
+                entries
+                    .into_iter()
+                    .flatten()
+                    .map(Some)
+                    .chain((!resident).then_some(None))
+            })
+            .filter(move |entry| entry.is_none_or(|(key, _)| !is_expired(&self.expiry, key, now)))
+    }
+
+    /// Returns iterator over entries which keys start with `prefix`. Only pages which may contain
+    /// such keys are loaded, one at a time as iteration reaches them, see `RangeIter`.
+    pub fn scan_prefix<'a>(
+        &'a mut self,
+        prefix: &'a K::Prefix,
+    ) -> impl Iterator<Item = Result<(K, V), DatabaseError>> + 'a
+    where
+        K: PrefixKey,
+        V: Clone,
+    {
+        let span = self.prefix_span(prefix);
+        let bounds = (Bound::Included(K::prefix_start(prefix)), Bound::Unbounded);
+
+        RangeIter::new(self, bounds, span, expiry::now()).take_while(move |entry| {
+            entry
+                .as_ref()
+                .map_or(true, |(key, _)| key.has_prefix(prefix))
+        })
+    }
+
+    pub fn succ(&mut self, key: &K) -> Result<Option<(&K, &V)>, DatabaseError> {
+        let now = expiry::now();
+
+        // Expired entries found on the way are removed, so the loop always moves forward.
+        loop {
+            let span = self.succ_span(key);
+
+            self.prepare(span.clone())?;
+
+            let expired = match span.clone().find_map(|index| self.pages[index].succ(key)) {
+                Some((next, _)) if self.is_expired(next, now) => next.clone(),
+                _ => break,
+            };
+
+            self.remove(&expired)?;
+        }
+
+        Ok(self
+            .succ_span(key)
+            .find_map(|index| self.pages[index].succ(key)))
+    }
+
+    /// Same as `succ` but returns `None` instead of loading swapped out pages or removing expired
+    /// entry.
+    pub fn try_succ(&self, key: &K) -> Option<Option<(&K, &V)>> {
+        let mut span = self.succ_span(key);
+
+        if !self.touch_resident(span.clone()) {
+            return None;
+        }
+
+        match span.find_map(|index| self.pages[index].succ(key)) {
+            Some((next, _)) if self.is_expired(next, expiry::now()) => None,
+            entry => Some(entry),
+        }
+    }
+
+    pub fn pred(&mut self, key: &K) -> Result<Option<(&K, &V)>, DatabaseError> {
+        let now = expiry::now();
+
+        loop {
+            let span = self.pred_span(key);
+
+            self.prepare(span.clone())?;
+
+            let expired = match span.rev().find_map(|index| self.pages[index].pred(key)) {
+                Some((previous, _)) if self.is_expired(previous, now) => previous.clone(),
+                _ => break,
+            };
+
+            self.remove(&expired)?;
+        }
+
+        Ok(self
+            .pred_span(key)
+            .rev()
+            .find_map(|index| self.pages[index].pred(key)))
+    }
+
+    /// Same as `pred` but returns `None` instead of loading swapped out pages or removing expired
+    /// entry.
+    pub fn try_pred(&self, key: &K) -> Option<Option<(&K, &V)>> {
+        let span = self.pred_span(key);
+
+        if !self.touch_resident(span.clone()) {
+            return None;
+        }
+
+        match span.rev().find_map(|index| self.pages[index].pred(key)) {
+            Some((previous, _)) if self.is_expired(previous, expiry::now()) => None,
+            entry => Some(entry),
+        }
+    }
+
+    /// Removes all entries expired before `now`. Returns number of removed entries.
+    pub fn remove_expired(&mut self, now: u64) -> Result<usize, DatabaseError> {
+        let expired: Vec<_> = self
+            .expiry
+            .iter()
+            .filter(|(_, &expires_at)| expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            self.remove(key)?;
+        }
+
+        Ok(expired.len())
+    }
+
+    /// Removes all entries within `range` and returns their number. Pages lying inside the range
+    /// are dropped without loading them from disk, boundary pages are trimmed.
+    pub fn remove_range<R>(&mut self, range: R) -> Result<usize, DatabaseError>
+    where
+        R: RangeBounds<K>,
+    {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+
+        if is_inverted(&bounds) {
+            return Ok(0);
+        }
+
+        split_range(&mut self.expiry, &bounds);
+
+        let span = self.bounds_span(&bounds);
+        let mut index = span.start;
+        let mut end = span.end;
+        let mut removed = 0;
+
+        while index < end {
+            let page = &self.pages[index];
+
+            if bounds.contains(page.range_start()) && bounds.contains(page.range_end()) {
+                removed += self.remove_page(index).size();
+                end -= 1;
+
+                continue;
+            }
+
+            self.touch(index)?;
+
+            let bytes = self.pages[index].bytes();
+
+            removed += self.pages[index].remove_range(&bounds);
+            self.lru.resize(bytes, self.pages[index].bytes());
+
+            if self.pages[index].size() == 0 {
+                self.remove_page(index);
+                end -= 1;
+            } else {
+                // Range start of the page may have moved.
+                self.lru.rekey(&self.pages[index]);
+                index += 1;
+            }
+        }
+
+        self.shrink(removed);
+
+        // Only the first and the last page of the range are trimmed, they are adjacent now.
+        let trimmed = span.start..self.pages.len().min(span.start + 2);
+
+        for index in trimmed.rev() {
+            if index < self.pages.len() && self.pages[index].size() < self.min_page_size() {
+                self.merge_underfilled(index)?;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Returns expiry time of entry in milliseconds since UNIX epoch, `None` if entry does not
+    /// expire.
+    pub fn expires_at(&self, key: &K) -> Option<u64> {
+        self.expiry.get(key).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns number of entries which are not expired at `now`.
+    pub fn live_size(&self, now: u64) -> usize {
+        let expired = self
+            .expiry
+            .values()
+            .filter(|&&expires_at| expires_at <= now)
+            .count();
+
+        self.size - expired
+    }
+
+    /// Returns number of entries in every page.
+    pub fn page_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pages.iter().map(|page| page.size())
+    }
+
+    /// Returns approximate size of resident pages in bytes.
+    pub fn memory(&self) -> usize {
+        self.lru.memory()
+    }
+
+    pub fn visit<T>(&self, visiter: &mut T) -> Result<(), DatabaseError>
+    where
+        T: TreeVisiter<K, V>,
+    {
+        for (index, page) in self.pages.iter().enumerate() {
+            page.visit(index, visiter)?;
+        }
+
+        Ok(())
+    }
+
+    /// Splits pages exceeding the current limits and merges under-filled pages, used after snapshot
+    /// saved with other page size is loaded.
+    pub fn repage(&mut self) -> Result<(), DatabaseError> {
+        let mut index = 0;
+
+        while index < self.pages.len() {
+            self.touch(index)?;
+
+            if self.pages[index].is_overfilled(&self.config) {
+                let bytes = self.pages[index].bytes();
+                let next = self.pages[index].split();
+
+                self.lru.resize(bytes, self.pages[index].bytes());
+                self.pages.insert(index + 1, next);
+                self.admit(index + 1);
+            } else {
+                index += 1;
+            }
+        }
+
+        self.compact()
+    }
+
+    /// Merges adjacent pages while at least one of them is under-filled and their entries fit into
+    /// single page.
+    pub fn compact(&mut self) -> Result<(), DatabaseError> {
+        let min_page_size = self.min_page_size();
+        let mut index = 0;
+
+        while index + 1 < self.pages.len() {
+            let size = self.pages[index].size();
+            let next_size = self.pages[index + 1].size();
+
+            if (size < min_page_size || next_size < min_page_size) && self.fits(index) {
+                self.merge(index)?;
+            } else {
+                index += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_expired(&self, key: &K, now: u64) -> bool {
+        is_expired(&self.expiry, key, now)
+    }
+
+    fn min_page_size(&self) -> usize {
+        (self.config.max_page_size() as f64 * self.config.min_page_fill()) as usize
+    }
+
+    /// Merges under-filled page with the next or the previous page if they fit into single page.
+    fn merge_underfilled(&mut self, index: usize) -> Result<(), DatabaseError> {
+        if index + 1 < self.pages.len() && self.fits(index) {
+            self.merge(index)
+        } else if index > 0 && self.fits(index - 1) {
+            self.merge(index - 1)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns `true` if entries of pages at `index` and `index + 1` fit into single page.
+    fn fits(&self, index: usize) -> bool {
+        let (page, next) = (&self.pages[index], &self.pages[index + 1]);
+
+        page.size() + next.size() <= self.config.max_page_size()
+            && self
+                .config
+                .max_page_bytes()
+                .is_none_or(|max| page.bytes() + next.bytes() <= max)
+    }
+
+    /// Moves entries of the page at `index + 1` into the page at `index`.
+    fn merge(&mut self, index: usize) -> Result<(), DatabaseError> {
+        self.prepare(index..index + 2)?;
+
+        let bytes = self.pages[index].bytes();
+        let next = self.remove_page(index + 1);
+
+        self.pages[index].merge(next);
+        self.lru.resize(bytes, self.pages[index].bytes());
+
+        Ok(())
+    }
+
+    fn remove_page(&mut self, index: usize) -> Page<K, V> {
+        let page = self.pages.remove(index);
+
+        if page.is_resident() {
+            self.lru.release(page.bytes());
+        }
+
+        self.counters.removed_pages.fetch_add(1, Ordering::Relaxed);
+
+        page
+    }
+
+    /// Counts page which was created or loaded from disk as resident.
+    fn admit(&mut self, index: usize) {
+        self.lru.admit(&self.pages[index]);
+        self.lru.compact(&self.pages);
+    }
+
+    fn grow(&mut self, count: usize) {
+        self.size += count;
+        self.counters.entries.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn shrink(&mut self, count: usize) {
+        self.size -= count;
+        self.counters.entries.fetch_sub(count, Ordering::Relaxed);
+    }
+
+    /// Appends page with entries following all existing keys. Entries are moved into the last
+    /// page if they fit into it, so partial pages of consecutive loads are joined.
+    fn append_page(&mut self, tree: BTreeMap<K, V>) -> Result<(), DatabaseError> {
+        let page = match Page::from_tree(tree) {
+            Some(page) => page,
+            None => return Ok(()),
+        };
+
+        self.grow(page.size());
+        self.pages.push(page);
+
+        let index = self.pages.len() - 1;
+
+        self.admit(index);
+
+        if index > 0 && self.fits(index - 1) {
+            self.merge(index - 1)
+        } else {
+            self.touch(index)
         }
+    }
+
+    /// Returns index of the last page which range starts before or at `key`.
+    fn page_index(&self, key: &K) -> Option<usize> {
+        match self.pages.partition_point(|page| page.range_start() <= key) {
+            0 => None,
+            index => Some(index - 1),
+        }
+    }
+
+    /// Returns pages which may contain `key`.
+    fn key_span(&self, key: &K) -> Range<usize> {
+        match self.page_index(key) {
+            Some(index) => index..index + 1,
+            None => 0..0,
+        }
+    }
+
+    /// Returns pages which may contain the next key after `key`. Pages are never empty, so it is
+    /// either the page of the `key` or the next one.
+    fn succ_span(&self, key: &K) -> Range<usize> {
+        let start = self.page_index(key).unwrap_or(0);
 
-        match self.pages.partition_point(|page| page.range_start() <= key) {
-            0 => false,
-            index => self.pages[index - 1].contains(key),
-        }
+        start..self.pages.len().min(start + 2)
     }
 
-    pub fn remove(&mut self, key: &K) -> bool {
-        if self.pages.is_empty() {
-            return false;
+    /// Returns pages which may contain the previous key before `key`.
+    fn pred_span(&self, key: &K) -> Range<usize> {
+        match self.page_index(key) {
+            Some(index) => index.saturating_sub(1)..index + 1,
+            None => 0..0,
         }
+    }
 
-        match self.pages.partition_point(|page| page.range_start() <= key) {
-            0 => false,
-            index => {
-                let index = index - 1;
-                let page = &mut self.pages[index];
-                let result = page.remove(key);
+    /// Returns pages which may contain keys from `key_first` to `key_last`.
+    fn range_span(&self, key_first: &K, key_last: &K) -> Range<usize> {
+        let start = self.page_index(key_first).unwrap_or(0);
+        let end = self
+            .pages
+            .partition_point(|page| page.range_start() <= key_last);
 
-                if result && page.size() == 0 {
-                    self.pages.remove(index);
-                }
+        start..end.max(start)
+    }
 
-                result
-            }
-        }
+    /// Returns pages which may contain keys within `bounds`.
+    fn bounds_span(&self, bounds: &(Bound<K>, Bound<K>)) -> Range<usize> {
+        let start = match &bounds.0 {
+            Bound::Included(key) | Bound::Excluded(key) => self.page_index(key).unwrap_or(0),
+            Bound::Unbounded => 0,
+        };
+        let end = match &bounds.1 {
+            Bound::Included(key) => self.pages.partition_point(|page| page.range_start() <= key),
+            Bound::Excluded(key) => self.pages.partition_point(|page| page.range_start() < key),
+            Bound::Unbounded => self.pages.len(),
+        };
+
+        start..end.max(start)
     }
 
-    pub fn range<F>(&self, key_first: &K, key_last: &K, mut callback: F)
+    /// Returns pages which may contain keys starting with `prefix`. Such keys are adjacent, so
+    /// every page after the first one must start with `prefix`.
+    fn prefix_span(&self, prefix: &K::Prefix) -> Range<usize>
     where
-        F: FnMut(&K, &V) -> bool,
+        K: PrefixKey,
     {
-        if self.pages.is_empty() {
-            return;
-        }
+        let start_key = K::prefix_start(prefix);
+        let start = self.page_index(&start_key).unwrap_or(0);
+        let end = self.pages.partition_point(|page| {
+            page.range_start() <= &start_key || page.range_start().has_prefix(prefix)
+        });
 
-        let start_page = match self
-            .pages
-            .partition_point(|page| page.range_start() <= key_first)
-        {
-            0 => 0,
-            index => index - 1,
-        };
+        start..end.max(start)
+    }
 
-        for page in &self.pages[start_page..] {
-            if page.range_start() > key_last {
-                break;
-            }
+    /// Marks pages as recently used. Returns `false` if any of them is swapped out.
+    fn touch_resident(&self, span: Range<usize>) -> bool {
+        let pages = &self.pages[span];
 
-            if !page.range(key_first, key_last, &mut callback) {
-                break;
+        if pages.iter().all(|page| page.is_resident()) {
+            for page in pages {
+                self.lru.touch(page);
             }
-        }
-    }
 
-    pub fn succ(&self, key: &K) -> Option<(&K, &V)> {
-        if self.pages.is_empty() {
-            return None;
+            true
+        } else {
+            false
         }
+    }
 
-        let start_page = match self.pages.partition_point(|page| page.range_start() <= key) {
-            0 => 0,
-            index => index - 1,
-        };
+    /// Marks page as recently used and loads it from disk if it was swapped out.
+    fn touch(&mut self, index: usize) -> Result<(), DatabaseError> {
+        self.prepare(index..index + 1)
+    }
 
-        for page in &self.pages[start_page..] {
-            if let Some(entry) = page.succ(key) {
-                return Some(entry);
+    /// Loads pages from disk and swaps out other pages if database exceeds limits.
+    fn prepare(&mut self, span: Range<usize>) -> Result<(), DatabaseError> {
+        for index in span.clone() {
+            if self.pages[index].is_resident() {
+                self.lru.touch(&self.pages[index]);
+            } else {
+                self.pages[index].swap_in()?;
+                self.admit(index);
             }
         }
 
-        None
+        self.evict(span)
     }
 
-    pub fn pred(&self, key: &K) -> Option<(&K, &V)> {
-        if self.pages.is_empty() {
-            return None;
-        }
-
-        let start_page = match self.pages.partition_point(|page| page.range_start() <= key) {
-            0 => 0,
-            index => index - 1,
-        };
+    /// Swaps out pages of partition while database exceeds limits. Used to free memory taken by
+    /// pages of other partitions.
+    pub fn reclaim(&mut self) -> Result<(), DatabaseError> {
+        self.evict(0..0)
+    }
 
-        for page in self.pages[..=start_page].iter().rev() {
-            if let Some(entry) = page.pred(key) {
-                return Some(entry);
-            }
+    /// Swaps least recently used pages except pages in `keep` to disk while resident pages of
+    /// database exceed `Config::max_pages` or `Config::memory_budget`.
+    fn evict(&mut self, keep: Range<usize>) -> Result<(), DatabaseError> {
+        while self.lru.residency().exceeds(&self.config) {
+            let index = match self.lru.victim(&self.pages, &keep) {
+                Some(index) => index,
+                None => break,
+            };
+            let page = &mut self.pages[index];
+
+            page.swap_out(self.config.storage_path())?;
+            self.lru.release(page.bytes());
         }
 
-        None
+        Ok(())
     }
+}
 
-    pub fn size(&self) -> usize {
-        self.pages.iter().map(|page| page.size()).sum()
+impl<K, V> Drop for Pages<K, V>
+where
+    K: Ord,
+{
+    fn drop(&mut self) {
+        self.counters
+            .entries
+            .fetch_sub(self.size, Ordering::Relaxed);
     }
+}
 
-    pub fn visit<T>(&self, visiter: &mut T)
-    where
-        T: TreeVisiter<K, V>,
-    {
-        for (index, page) in self.pages.iter().enumerate() {
-            page.visit(index, visiter);
+fn is_expired<K>(expiry: &BTreeMap<K, u64>, key: &K, now: u64) -> bool
+where
+    K: Ord,
+{
+    match expiry.get(key) {
+        Some(&expires_at) => expires_at <= now,
+        None => false,
+    }
+}
+
+/// Removes entries within `bounds` from `tree` and returns them.
+fn split_range<K, V>(tree: &mut BTreeMap<K, V>, bounds: &(Bound<K>, Bound<K>)) -> BTreeMap<K, V>
+where
+    K: Ord + Clone,
+{
+    let mut middle = match &bounds.0 {
+        Bound::Included(key) => tree.split_off(key),
+        Bound::Excluded(key) => {
+            let mut middle = tree.split_off(key);
+
+            if let Some(value) = middle.remove(key) {
+                tree.insert(key.clone(), value);
+            }
+
+            middle
+        }
+        Bound::Unbounded => mem::take(tree),
+    };
+    let mut tail = match &bounds.1 {
+        Bound::Included(key) => {
+            let mut tail = middle.split_off(key);
+
+            if let Some(value) = tail.remove(key) {
+                middle.insert(key.clone(), value);
+            }
+
+            tail
         }
+        Bound::Excluded(key) => middle.split_off(key),
+        Bound::Unbounded => BTreeMap::new(),
+    };
+
+    tree.append(&mut tail);
+
+    middle
+}
+
+/// Returns `true` if start of `bounds` is after its end, such bounds can not be passed to
+/// `BTreeMap::range`.
+fn is_inverted<K>(bounds: &(Bound<K>, Bound<K>)) -> bool
+where
+    K: Ord,
+{
+    match bounds {
+        (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        (
+            Bound::Included(start) | Bound::Excluded(start),
+            Bound::Included(end) | Bound::Excluded(end),
+        ) => start > end,
+        _ => false,
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::pages::Counters;
     use crate::pages::Pages;
     use crate::visiter::TreeVisiter;
     use crate::Config;
-    use std::rc::Rc;
+    use std::env;
+    use std::fs;
+    use std::ops::Bound;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::Arc;
+
+    fn storage_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("htdb-pages-{}-{}", name, process::id()))
+    }
+
+    fn swap_files(path: &Path) -> usize {
+        fs::read_dir(path)
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+    }
 
     #[derive(Default)]
     struct CollectVisiter<K, V> {
@@ -222,293 +1161,827 @@ mod tests {
 
     #[test]
     fn get_must_return_none_if_pages_empty() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
-        let pages: Pages<usize, usize> = Pages::new(config);
+        let config = Arc::new(Config::default().set_max_page_size(3));
+        let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        assert_eq!(None, pages.get(&10));
+        assert_eq!(None, pages.get(&10).unwrap());
     }
 
     #[test]
     fn get_must_return_none_if_value_not_exists() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         for index in (10..=20).step_by(2) {
-            pages.insert(index, 0);
+            pages.insert(index, 0).unwrap();
         }
 
-        assert_eq!(None, pages.get(&9));
-        assert_eq!(None, pages.get(&11));
-        assert_eq!(None, pages.get(&17));
-        assert_eq!(None, pages.get(&25));
+        assert_eq!(None, pages.get(&9).unwrap());
+        assert_eq!(None, pages.get(&11).unwrap());
+        assert_eq!(None, pages.get(&17).unwrap());
+        assert_eq!(None, pages.get(&25).unwrap());
     }
 
     #[test]
     fn get_must_return_value_if_value_exists() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         for index in (10..=20).step_by(2) {
-            pages.insert(index, 0);
+            pages.insert(index, 0).unwrap();
         }
 
-        assert_eq!(Some(&0), pages.get(&10));
-        assert_eq!(Some(&0), pages.get(&12));
-        assert_eq!(Some(&0), pages.get(&18));
-        assert_eq!(Some(&0), pages.get(&20));
+        assert_eq!(Some(&0), pages.get(&10).unwrap());
+        assert_eq!(Some(&0), pages.get(&12).unwrap());
+        assert_eq!(Some(&0), pages.get(&18).unwrap());
+        assert_eq!(Some(&0), pages.get(&20).unwrap());
     }
 
     #[test]
     fn insert_must_add_value_if_empty() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        assert_eq!(true, pages.insert(10, 10));
+        assert_eq!(true, pages.insert(10, 10).unwrap());
     }
 
     #[test]
     fn insert_must_replace_value_if_empty() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        assert_eq!(true, pages.insert(10, 10));
-        assert_eq!(false, pages.insert(10, 20));
-        assert_eq!(Some(&20), pages.get(&10));
+        assert_eq!(true, pages.insert(10, 10).unwrap());
+        assert_eq!(false, pages.insert(10, 20).unwrap());
+        assert_eq!(Some(&20), pages.get(&10).unwrap());
     }
 
     #[test]
     fn contains_must_return_false_if_pages_empty() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
-        let pages: Pages<usize, usize> = Pages::new(config);
+        let config = Arc::new(Config::default().set_max_page_size(3));
+        let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        assert_eq!(false, pages.contains(&10));
+        assert_eq!(false, pages.contains(&10).unwrap());
     }
 
     #[test]
     fn contains_must_return_false_if_value_not_exists() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         for index in (10..=20).step_by(2) {
-            pages.insert(index, 0);
+            pages.insert(index, 0).unwrap();
         }
 
-        assert_eq!(false, pages.contains(&9));
-        assert_eq!(false, pages.contains(&11));
-        assert_eq!(false, pages.contains(&17));
-        assert_eq!(false, pages.contains(&25));
+        assert_eq!(false, pages.contains(&9).unwrap());
+        assert_eq!(false, pages.contains(&11).unwrap());
+        assert_eq!(false, pages.contains(&17).unwrap());
+        assert_eq!(false, pages.contains(&25).unwrap());
     }
 
     #[test]
     fn contains_must_return_true_if_value_exists() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         for index in (10..=20).step_by(2) {
-            pages.insert(index, 0);
+            pages.insert(index, 0).unwrap();
         }
 
-        assert_eq!(true, pages.contains(&10));
-        assert_eq!(true, pages.contains(&12));
-        assert_eq!(true, pages.contains(&18));
-        assert_eq!(true, pages.contains(&20));
+        assert_eq!(true, pages.contains(&10).unwrap());
+        assert_eq!(true, pages.contains(&12).unwrap());
+        assert_eq!(true, pages.contains(&18).unwrap());
+        assert_eq!(true, pages.contains(&20).unwrap());
     }
 
     #[test]
     fn remove_must_return_false_if_pages_empty() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        assert_eq!(false, pages.remove(&10));
+        assert_eq!(None, pages.remove(&10).unwrap());
     }
 
     #[test]
     fn remove_must_return_false_if_value_not_exists() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         for index in (10..=20).step_by(2) {
-            pages.insert(index, 0);
+            pages.insert(index, 0).unwrap();
         }
 
-        assert_eq!(false, pages.remove(&9));
-        assert_eq!(false, pages.remove(&11));
-        assert_eq!(false, pages.remove(&17));
-        assert_eq!(false, pages.remove(&25));
+        assert_eq!(None, pages.remove(&9).unwrap());
+        assert_eq!(None, pages.remove(&11).unwrap());
+        assert_eq!(None, pages.remove(&17).unwrap());
+        assert_eq!(None, pages.remove(&25).unwrap());
     }
 
     #[test]
     fn remove_must_return_true_if_value_exists() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         for index in (10..=20).step_by(2) {
-            pages.insert(index, 0);
+            pages.insert(index, 0).unwrap();
         }
 
-        assert_eq!(true, pages.remove(&10));
-        assert_eq!(true, pages.remove(&12));
-        assert_eq!(true, pages.remove(&18));
-        assert_eq!(true, pages.remove(&20));
+        assert_eq!(Some(0), pages.remove(&10).unwrap());
+        assert_eq!(Some(0), pages.remove(&12).unwrap());
+        assert_eq!(Some(0), pages.remove(&18).unwrap());
+        assert_eq!(Some(0), pages.remove(&20).unwrap());
         assert_eq!(2, pages.size());
     }
 
     #[test]
     fn size_must_return_number_of_elements() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
         assert_eq!(0, pages.size());
 
         for index in 0..10 {
-            pages.insert(index, index);
+            pages.insert(index, index).unwrap();
             assert_eq!(index + 1, pages.size());
         }
 
         for index in 0..10 {
-            pages.insert(index, index);
+            pages.insert(index, index).unwrap();
             assert_eq!(10, pages.size());
         }
     }
 
     #[test]
     fn range_must_select_none() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
-        let pages: Pages<usize, usize> = Pages::new(config);
+        let config = Arc::new(Config::default().set_max_page_size(4));
+        let mut pages: Pages<usize, usize> = Pages::new(config);
         let mut result = Vec::new();
 
-        pages.range(&0, &0, |&k, &v| {
-            result.push((k, v));
+        pages
+            .range(&0, &0, |&k, &v| {
+                result.push((k, v));
 
-            true
-        });
+                true
+            })
+            .unwrap();
 
         assert_eq!(true, result.is_empty());
     }
 
     #[test]
     fn range_must_select_one_when_breaked() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
         let mut pages: Pages<usize, usize> = Pages::new(config);
         let mut result = Vec::new();
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
-        pages.range(&0, &3, |&k, &v| {
-            result.push((k, v));
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
+        pages
+            .range(&0, &3, |&k, &v| {
+                result.push((k, v));
 
-            false
-        });
+                false
+            })
+            .unwrap();
 
         assert_eq!(vec![(1, 10)], result);
     }
 
     #[test]
     fn range_must_select_page_values() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
         let mut pages: Pages<usize, usize> = Pages::new(config);
         let mut result = Vec::new();
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
-        pages.insert(3, 30);
-        pages.insert(4, 40);
-        pages.range(&2, &3, |&k, &v| {
-            result.push((k, v));
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
+        pages.insert(3, 30).unwrap();
+        pages.insert(4, 40).unwrap();
+        pages
+            .range(&2, &3, |&k, &v| {
+                result.push((k, v));
 
-            true
-        });
+                true
+            })
+            .unwrap();
 
         assert_eq!(vec![(2, 20), (3, 30)], result);
     }
 
     #[test]
     fn range_must_select_all_values() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
         let mut pages: Pages<usize, usize> = Pages::new(config);
         let mut result = Vec::new();
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
-        pages.insert(3, 30);
-        pages.insert(4, 40);
-        pages.insert(5, 50);
-        pages.range(&2, &4, |&k, &v| {
-            result.push((k, v));
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
+        pages.insert(3, 30).unwrap();
+        pages.insert(4, 40).unwrap();
+        pages.insert(5, 50).unwrap();
+        pages
+            .range(&2, &4, |&k, &v| {
+                result.push((k, v));
 
-            true
-        });
+                true
+            })
+            .unwrap();
 
         assert_eq!(vec![(2, 20), (3, 30), (4, 40)], result);
     }
 
     #[test]
     fn succ_must_select_next_value() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
 
-        assert_eq!(Some((&2, &20)), pages.succ(&1));
+        assert_eq!(Some((&2, &20)), pages.succ(&1).unwrap());
     }
 
     #[test]
     fn succ_must_select_next_oage() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
-        pages.insert(3, 30);
-        pages.insert(4, 40);
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
+        pages.insert(3, 30).unwrap();
+        pages.insert(4, 40).unwrap();
 
-        assert_eq!(Some((&3, &30)), pages.succ(&2));
+        assert_eq!(Some((&3, &30)), pages.succ(&2).unwrap());
     }
 
     #[test]
     fn succ_must_select_none() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
 
-        assert_eq!(None, pages.succ(&2));
+        assert_eq!(None, pages.succ(&2).unwrap());
     }
 
     #[test]
     fn pred_must_select_next_value() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
 
-        assert_eq!(Some((&1, &10)), pages.pred(&2));
+        assert_eq!(Some((&1, &10)), pages.pred(&2).unwrap());
     }
 
     #[test]
     fn pred_must_select_next_oage() {
-        let config = Rc::new(Config::default().set_max_page_size(3));
+        let config = Arc::new(Config::default().set_max_page_size(3));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
-        pages.insert(3, 30);
-        pages.insert(4, 40);
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
+        pages.insert(3, 30).unwrap();
+        pages.insert(4, 40).unwrap();
 
-        assert_eq!(Some((&2, &20)), pages.pred(&3));
+        assert_eq!(Some((&2, &20)), pages.pred(&3).unwrap());
     }
 
     #[test]
     fn pred_must_select_none() {
-        let config = Rc::new(Config::default().set_max_page_size(4));
+        let config = Arc::new(Config::default().set_max_page_size(4));
+        let mut pages: Pages<usize, usize> = Pages::new(config);
+
+        pages.insert(1, 10).unwrap();
+        pages.insert(2, 20).unwrap();
+
+        assert_eq!(None, pages.pred(&1).unwrap());
+    }
+
+    #[test]
+    fn insert_must_swap_pages_to_disk() {
+        let path = storage_path("insert");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(2))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        assert_eq!(
+            2,
+            pages.pages.iter().filter(|page| page.is_resident()).count()
+        );
+        assert_eq!(pages.pages.len() - 2, swap_files(&path));
+        assert_eq!(10, pages.size());
+    }
+
+    #[test]
+    fn get_must_load_swapped_pages() {
+        let path = storage_path("get");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        for index in 0..10 {
+            assert_eq!(Some(&(index * 10)), pages.get(&index).unwrap());
+        }
+
+        assert_eq!(
+            1,
+            pages.pages.iter().filter(|page| page.is_resident()).count()
+        );
+        assert_eq!(Some((&5, &50)), pages.succ(&4).unwrap());
+        assert_eq!(Some((&3, &30)), pages.pred(&4).unwrap());
+    }
+
+    #[test]
+    fn try_get_must_skip_swapped_pages() {
+        let path = storage_path("try-get");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        assert_eq!(None, pages.try_get(&0));
+        assert_eq!(None, pages.try_contains(&0));
+        assert_eq!(Some(Some(&90)), pages.try_get(&9));
+        assert_eq!(Some(true), pages.try_contains(&9));
+        assert_eq!(Some(None), pages.try_succ(&9));
+    }
+
+    #[test]
+    fn visit_must_read_swapped_pages() {
+        let path = storage_path("visit");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+        let mut visiter = CollectVisiter::default();
+
+        for index in 0..6 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        pages.visit(&mut visiter).unwrap();
+
+        assert_eq!(
+            vec![(0, 0), (1, 10), (2, 20), (3, 30), (4, 40), (5, 50)],
+            visiter.pages.concat()
+        );
+    }
+
+    #[test]
+    fn drop_must_remove_swap_files() {
+        let path = storage_path("drop");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, index).unwrap();
+        }
+
+        assert_ne!(0, swap_files(&path));
+
+        drop(pages);
+
+        assert_eq!(0, swap_files(&path));
+    }
+
+    #[test]
+    fn range_iter_must_load_swapped_pages() {
+        let path = storage_path("range-iter");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        let keys: Vec<_> = pages
+            .range_iter(1..9)
+            .map(|entry| entry.unwrap().0)
+            .collect();
+
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], keys);
+
+        let keys: Vec<_> = pages
+            .range_iter(..)
+            .rev()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+
+        assert_eq!(vec![9, 8, 7, 6, 5, 4, 3, 2, 1, 0], keys);
+    }
+
+    #[test]
+    fn range_iter_must_load_pages_on_demand() {
+        let path = storage_path("range-iter-lazy");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        pages.get(&0).unwrap();
+
+        let keys: Vec<_> = pages
+            .range_iter(..)
+            .take(3)
+            .map(|entry| entry.unwrap().0)
+            .collect();
+
+        assert_eq!(vec![0, 1, 2], keys);
+        assert_eq!(
+            true,
+            pages.pages.iter().filter(|page| page.is_resident()).count() <= 3
+        );
+        assert_eq!(false, pages.pages[pages.pages.len() - 1].is_resident());
+    }
+
+    #[test]
+    fn range_iter_must_keep_resident_pages_within_limit() {
+        let path = storage_path("range-iter-limit");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(2))
+            .set_storage_path(&path);
+        let counters = Arc::new(Counters::default());
+        let mut pages: Pages<usize, usize> =
+            Pages::with_counters(Arc::new(config), counters.clone());
+
+        for index in 0..40 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        let mut count = 0;
+
+        for entry in pages.range_iter(..) {
+            assert_eq!(count, entry.unwrap().0);
+            assert_eq!(true, counters.residency().pages() <= 2);
+
+            count += 1;
+        }
+
+        assert_eq!(40, count);
+    }
+
+    #[test]
+    fn scan_prefix_must_return_matching_keys() {
+        let config = Arc::new(Config::default().set_max_page_size(2));
+        let mut pages: Pages<String, usize> = Pages::new(config);
+
+        for (index, key) in ["a", "ab", "abc", "abd", "ac", "b", "ba"]
+            .iter()
+            .enumerate()
+        {
+            pages.insert(key.to_string(), index).unwrap();
+        }
+
+        let keys: Vec<_> = pages
+            .scan_prefix("ab")
+            .map(|entry| entry.unwrap().0)
+            .collect();
+
+        assert_eq!(vec!["ab", "abc", "abd"], keys);
+        assert_eq!(0, pages.scan_prefix("c").count());
+        assert_eq!(7, pages.scan_prefix("").count());
+    }
+
+    #[test]
+    fn scan_prefix_must_keep_resident_pages_within_limit() {
+        let path = storage_path("scan-prefix-limit");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(2))
+            .set_storage_path(&path);
+        let counters = Arc::new(Counters::default());
+        let mut pages: Pages<String, usize> =
+            Pages::with_counters(Arc::new(config), counters.clone());
+
+        for index in 0..40 {
+            pages.insert(format!("{:02}", index), index).unwrap();
+        }
+
+        for entry in pages.scan_prefix("") {
+            entry.unwrap();
+
+            assert_eq!(true, counters.residency().pages() <= 2);
+        }
+    }
+
+    #[test]
+    fn expired_entries_must_be_invisible() {
+        let config = Arc::new(Config::default().set_max_page_size(2));
+        let mut pages: Pages<usize, usize> = Pages::new(config);
+        let mut keys = Vec::new();
+
+        for index in 0..6 {
+            pages.insert(index, index).unwrap();
+        }
+
+        pages.insert_with_expiry(2, 20, 0).unwrap();
+        pages.insert_with_expiry(3, 30, 0).unwrap();
+        pages.insert_with_expiry(4, 40, u64::MAX).unwrap();
+
+        assert_eq!(None, pages.try_get(&2).unwrap());
+        assert_eq!(None, pages.try_succ(&1));
+        assert_eq!(Some((&4, &40)), pages.succ(&1).unwrap());
+        assert_eq!(Some((&1, &1)), pages.pred(&4).unwrap());
+        assert_eq!(false, pages.contains(&3).unwrap());
+        assert_eq!(Some(&40), pages.get(&4).unwrap());
+
+        pages
+            .range(&0, &5, |&key, _| {
+                keys.push(key);
+
+                true
+            })
+            .unwrap();
+
+        assert_eq!(vec![0, 1, 4, 5], keys);
+        assert_eq!(4, pages.size());
+    }
+
+    #[test]
+    fn remove_expired_must_remove_only_expired_entries() {
+        let config = Arc::new(Config::default().set_max_page_size(2));
         let mut pages: Pages<usize, usize> = Pages::new(config);
 
-        pages.insert(1, 10);
-        pages.insert(2, 20);
+        for index in 0..6 {
+            let expires_at = if index < 4 { 0 } else { u64::MAX };
 
-        assert_eq!(None, pages.pred(&1));
+            pages.insert_with_expiry(index, index, expires_at).unwrap();
+        }
+
+        pages.insert(0, 0).unwrap();
+
+        assert_eq!(3, pages.remove_expired(1).unwrap());
+        assert_eq!(
+            vec![0, 4, 5],
+            pages
+                .range_iter(..)
+                .map(|entry| entry.unwrap().0)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn update_must_change_insert_and_remove_values() {
+        let config = Arc::new(Config::default().set_max_page_size(2));
+        let mut pages: Pages<usize, usize> = Pages::new(config);
+
+        for index in 0..4 {
+            assert_eq!(
+                None,
+                pages.update(index, |value| value.replace(index)).unwrap()
+            );
+        }
+
+        let page_count = pages.pages.len();
+
+        pages
+            .update(2, |value| {
+                if let Some(value) = value {
+                    *value += 10;
+                }
+            })
+            .unwrap();
+        pages.update(4, |value| *value = Some(40)).unwrap();
+        pages.update(5, |value| *value = Some(50)).unwrap();
+
+        assert_eq!(true, pages.pages.len() > page_count);
+        assert_eq!(Some(1), pages.update(1, |value| value.take()).unwrap());
+        assert_eq!(Some(&12), pages.get(&2).unwrap());
+        assert_eq!(false, pages.contains(&1).unwrap());
+        assert_eq!(5, pages.size());
+    }
+
+    #[test]
+    fn remove_must_merge_underfilled_pages() {
+        let config = Config::default()
+            .set_max_page_size(4)
+            .set_min_page_fill(0.5);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..8 {
+            pages.insert(index, index).unwrap();
+        }
+
+        let page_count = pages.pages.len();
+
+        for index in 1..7 {
+            pages.remove(&index).unwrap();
+        }
+
+        assert_eq!(true, pages.pages.len() < page_count);
+        assert_eq!(Some(&0), pages.get(&0).unwrap());
+        assert_eq!(Some(&7), pages.get(&7).unwrap());
+        assert_eq!(Some((&7, &7)), pages.succ(&0).unwrap());
+        assert_eq!(2, pages.size());
+    }
+
+    #[test]
+    fn compact_must_merge_underfilled_pages() {
+        let config = Config::default()
+            .set_max_page_size(4)
+            .set_min_page_fill(0.0);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+        let mut visiter = CollectVisiter::default();
+
+        for index in 0..16 {
+            pages.insert(index, index).unwrap();
+        }
+
+        for index in (0..16).filter(|index| index % 4 != 0) {
+            pages.remove(&index).unwrap();
+        }
+
+        pages.set_config(Arc::new(
+            Config::default()
+                .set_max_page_size(4)
+                .set_min_page_fill(0.5),
+        ));
+        pages.compact().unwrap();
+        pages.visit(&mut visiter).unwrap();
+
+        assert_eq!(vec![vec![(0, 0), (4, 4), (8, 8), (12, 12)]], visiter.pages);
+    }
+
+    #[test]
+    fn bulk_load_must_build_full_pages() {
+        let config = Config::default().set_max_page_size(4);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+        let mut visiter = CollectVisiter::default();
+
+        assert_eq!(
+            10,
+            pages
+                .bulk_load((0..10).map(|index| (index, index)))
+                .unwrap()
+        );
+
+        pages.visit(&mut visiter).unwrap();
+
+        assert_eq!(
+            vec![
+                vec![(0, 0), (1, 1), (2, 2), (3, 3)],
+                vec![(4, 4), (5, 5), (6, 6), (7, 7)],
+                vec![(8, 8), (9, 9)],
+            ],
+            visiter.pages
+        );
+    }
+
+    #[test]
+    fn bulk_load_must_merge_partial_pages() {
+        let config = Config::default()
+            .set_max_page_size(4)
+            .set_min_page_fill(0.5);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+        let mut visiter = CollectVisiter::default();
+
+        pages.bulk_load([(1, 1), (2, 2)]).unwrap();
+        pages.bulk_load([(3, 3), (0, 0), (4, 4)]).unwrap();
+        pages
+            .bulk_load((5..10).map(|index| (index, index)))
+            .unwrap();
+        pages.visit(&mut visiter).unwrap();
+
+        assert_eq!(
+            vec![
+                vec![(0, 0)],
+                vec![(1, 1), (2, 2), (3, 3), (4, 4)],
+                vec![(5, 5), (6, 6), (7, 7), (8, 8)],
+                vec![(9, 9)],
+            ],
+            visiter.pages
+        );
+    }
+
+    #[test]
+    fn bulk_load_must_insert_out_of_order_entries() {
+        let config = Config::default().set_max_page_size(4);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        pages.insert(5, 0).unwrap();
+
+        let entries = vec![(6, 6), (7, 7), (1, 1), (5, 5), (8, 8), (2, 2)];
+
+        assert_eq!(5, pages.bulk_load(entries).unwrap());
+
+        for (key, value) in [(1, 1), (2, 2), (5, 5), (6, 6), (7, 7), (8, 8)] {
+            assert_eq!(Some(&value), pages.get(&key).unwrap());
+        }
+
+        assert_eq!(6, pages.size());
+    }
+
+    #[test]
+    fn insert_must_split_pages_by_bytes() {
+        let config = Config::default()
+            .set_max_page_size(100)
+            .set_max_page_bytes(Some(200));
+        let mut pages: Pages<usize, Vec<u8>> = Pages::new(Arc::new(config));
+
+        for index in 0..10 {
+            pages.insert(index, vec![0; 100]).unwrap();
+        }
+
+        assert_eq!(10, pages.pages.len());
+        assert_eq!(true, pages.pages.iter().all(|page| page.bytes() <= 200));
+
+        pages.remove(&5).unwrap();
+
+        assert_eq!(9, pages.pages.len());
+    }
+
+    #[test]
+    fn memory_budget_must_swap_pages_to_disk() {
+        let path = storage_path("budget");
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_memory_budget(Some(100))
+            .set_storage_path(&path);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+
+        for index in 0..20 {
+            pages.insert(index, index * 10).unwrap();
+        }
+
+        assert_eq!(true, pages.memory() <= 100);
+        assert_eq!(true, swap_files(&path) > 0);
+
+        for index in 0..20 {
+            assert_eq!(Some(&(index * 10)), pages.get(&index).unwrap());
+        }
+
+        assert_eq!(true, pages.memory() <= 100);
+        assert_eq!(20, pages.size());
+    }
+
+    #[test]
+    fn remove_range_must_drop_inner_pages_and_trim_boundaries() {
+        let config = Config::default()
+            .set_max_page_size(4)
+            .set_min_page_fill(0.0);
+        let mut pages: Pages<usize, usize> = Pages::new(Arc::new(config));
+        let mut visiter = CollectVisiter::default();
+
+        pages
+            .bulk_load((0..16).map(|index| (index, index)))
+            .unwrap();
+
+        assert_eq!(9, pages.remove_range(2..11).unwrap());
+        assert_eq!(
+            0,
+            pages
+                .remove_range((Bound::Included(14), Bound::Excluded(12)))
+                .unwrap()
+        );
+        assert_eq!(
+            2,
+            pages
+                .remove_range((Bound::Excluded(12), Bound::Included(14)))
+                .unwrap()
+        );
+
+        pages.visit(&mut visiter).unwrap();
+
+        assert_eq!(
+            vec![
+                vec![(0, 0), (1, 1)],
+                vec![(11, 11)],
+                vec![(12, 12), (15, 15)],
+            ],
+            visiter.pages
+        );
+        assert_eq!(Some((&11, &11)), pages.succ(&1).unwrap());
     }
 }
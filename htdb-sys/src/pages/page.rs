@@ -1,11 +1,26 @@
+use super::split_range;
+use super::swap::SwapFile;
+use crate::config::Config;
+use crate::size;
 use crate::visiter::TreeVisiter;
+use crate::DatabaseError;
+use crate::EstimateSize;
+use serde::de::DeserializeOwned;
+use serde::ser::Error as SerError;
+use serde::ser::SerializeStruct;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::Serializer;
+use std::collections::btree_map;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::ops::Bound;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct Page<K, V>
 where
     K: Ord,
@@ -13,12 +28,19 @@ where
     range_start: K,
     range_end: K,
     tree: BTreeMap<K, V>,
+    /// Approximate size of entries, kept while page is swapped out.
+    #[serde(skip)]
+    bytes: usize,
+    #[serde(skip)]
+    swap: Option<SwapFile>,
+    #[serde(skip)]
+    access: AtomicU64,
 }
 
 impl<K, V> Page<K, V>
 where
-    K: Ord + Clone + Debug,
-    V: Debug,
+    K: Ord + Clone + Debug + EstimateSize,
+    V: Debug + EstimateSize,
 {
     #[cfg(test)]
     pub fn from_range(range_start: K, range_end: K) -> Page<K, V> {
@@ -26,6 +48,9 @@ where
             range_start,
             range_end,
             tree: BTreeMap::new(),
+            bytes: 0,
+            swap: None,
+            access: AtomicU64::new(0),
         }
     }
 
@@ -33,10 +58,28 @@ where
         Page {
             range_start: key.clone(),
             range_end: key.clone(),
+            bytes: size::entry_size(&key, &value),
             tree: BTreeMap::from([(key, value)]),
+            swap: None,
+            access: AtomicU64::new(0),
         }
     }
 
+    /// Creates page holding all entries of `tree`, returns `None` if `tree` is empty.
+    pub(crate) fn from_tree(tree: BTreeMap<K, V>) -> Option<Page<K, V>> {
+        let range_start = tree.keys().next()?.clone();
+        let range_end = tree.keys().next_back()?.clone();
+
+        Some(Page {
+            range_start,
+            range_end,
+            bytes: tree_size(&tree),
+            tree,
+            swap: None,
+            access: AtomicU64::new(0),
+        })
+    }
+
     pub fn range_start(&self) -> &K {
         &self.range_start
     }
@@ -49,20 +92,48 @@ where
         self.range_end = range_end;
     }
 
+    pub fn access(&self) -> u64 {
+        self.access.load(Ordering::Relaxed)
+    }
+
+    pub fn set_access(&self, access: u64) {
+        self.access.store(access, Ordering::Relaxed);
+    }
+
+    pub fn is_resident(&self) -> bool {
+        self.swap.is_none()
+    }
+
     pub fn get(&self, key: &K) -> Option<&V> {
         self.tree.get(key)
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> bool {
-        self.tree.insert(key, value).is_none()
+    /// Inserts value and returns previous value stored by the key.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let key_size = key.estimate_size();
+
+        self.bytes += key_size + value.estimate_size();
+
+        let previous = self.tree.insert(key, value);
+
+        if let Some(previous) = &previous {
+            self.bytes -= key_size + previous.estimate_size();
+        }
+
+        previous
     }
 
     pub fn contains(&self, key: &K) -> bool {
         self.tree.contains_key(key)
     }
 
-    pub fn remove(&mut self, key: &K) -> bool {
-        self.tree.remove(key).is_some()
+    /// Removes value and returns it.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.tree.remove(key)?;
+
+        self.bytes -= size::entry_size(key, &value);
+
+        Some(value)
     }
 
     /// Returns `true` if next page must be processed, otherwise returns `false`.
@@ -79,6 +150,29 @@ where
         true
     }
 
+    /// Returns iterator over entries in `range`. Start of `range` must not be greater than its end.
+    pub fn range_iter<R>(&self, range: R) -> btree_map::Range<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        self.tree.range(range)
+    }
+
+    /// Removes all entries within `bounds` and returns their number. Page must be resident and
+    /// stays non-empty range bounds if any entry is left.
+    pub fn remove_range(&mut self, bounds: &(Bound<K>, Bound<K>)) -> usize {
+        let removed = split_range(&mut self.tree, bounds);
+
+        self.bytes -= tree_size(&removed);
+
+        if let (Some(first), Some(last)) = (self.tree.keys().next(), self.tree.keys().next_back()) {
+            self.range_start = first.clone();
+            self.range_end = last.clone();
+        }
+
+        removed.len()
+    }
+
     pub fn succ(&self, key: &K) -> Option<(&K, &V)> {
         self.tree
             .range((Bound::Excluded(key), Bound::Unbounded))
@@ -88,12 +182,34 @@ where
     pub fn pred(&self, key: &K) -> Option<(&K, &V)> {
         self.tree
             .range((Bound::Unbounded, Bound::Excluded(key)))
-            .rev()
-            .next()
+            .next_back()
     }
 
     pub fn size(&self) -> usize {
-        self.tree.len()
+        match &self.swap {
+            Some(swap) => swap.size(),
+            None => self.tree.len(),
+        }
+    }
+
+    /// Returns approximate size of page entries in bytes.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Estimates size of page entries, size is not stored with page and must be estimated after
+    /// deserialization.
+    pub fn measure(&mut self) {
+        self.bytes = tree_size(&self.tree);
+    }
+
+    /// Returns `true` if page exceeds `Config::max_page_size` or `Config::max_page_bytes` and has
+    /// more than one entry to split.
+    pub fn is_overfilled(&self, config: &Config) -> bool {
+        let size = self.size();
+
+        size > config.max_page_size()
+            || (size > 1 && config.max_page_bytes().is_some_and(|max| self.bytes > max))
     }
 
     pub fn split(&mut self) -> Page<K, V> {
@@ -102,13 +218,20 @@ where
 
         if let Some(key) = self.tree.keys().nth(middle).cloned() {
             let tree = self.tree.split_off(&key);
+            let bytes = tree_size(&tree);
+
+            self.bytes -= bytes;
+
             let next = Page {
                 range_start: key,
                 range_end: self.range_end.clone(),
                 tree,
+                bytes,
+                swap: None,
+                access: AtomicU64::new(self.access()),
             };
 
-            if let Some(key) = self.tree.keys().rev().next() {
+            if let Some(key) = self.tree.keys().next_back() {
                 self.range_end = key.clone();
             }
 
@@ -118,23 +241,109 @@ where
         }
     }
 
-    pub fn visit<T>(&self, index: usize, visiter: &mut T)
+    /// Moves all entries of the `next` page into this page. Both pages must be resident.
+    pub fn merge(&mut self, mut next: Page<K, V>) {
+        self.tree.append(&mut next.tree);
+        self.bytes += next.bytes;
+        self.range_end = next.range_end;
+    }
+
+    pub fn visit<T>(&self, index: usize, visiter: &mut T) -> Result<(), DatabaseError>
     where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
         T: TreeVisiter<K, V>,
     {
         visiter.visit_page_before(index, &self.range_start, &self.range_end);
 
-        for (key, value) in &self.tree {
-            visiter.visit_value(key, value);
+        match &self.swap {
+            Some(swap) => {
+                for (key, value) in &swap.read()? {
+                    visiter.visit_value(key, value);
+                }
+            }
+            None => {
+                for (key, value) in &self.tree {
+                    visiter.visit_value(key, value);
+                }
+            }
         }
 
         visiter.visit_page_after(index, &self.range_start, &self.range_end);
+
+        Ok(())
     }
+
+    /// Writes page entries to the swap file in `directory` and releases them from memory.
+    pub fn swap_out(&mut self, directory: &Path) -> Result<(), DatabaseError>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        if self.swap.is_none() {
+            self.swap = Some(SwapFile::write(directory, &self.tree)?);
+            self.tree.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Reads page entries back from the swap file and removes the file.
+    pub fn swap_in(&mut self) -> Result<(), DatabaseError>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        if let Some(swap) = &self.swap {
+            self.tree = swap.read()?;
+            self.swap = None;
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V> Serialize for Page<K, V>
+where
+    K: Ord + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Page", 3)?;
+        state.serialize_field("range_start", &self.range_start)?;
+        state.serialize_field("range_end", &self.range_end)?;
+
+        match &self.swap {
+            Some(swap) => {
+                let tree: BTreeMap<K, V> = swap.read().map_err(S::Error::custom)?;
+
+                state.serialize_field("tree", &tree)?;
+            }
+            None => state.serialize_field("tree", &self.tree)?,
+        }
+
+        state.end()
+    }
+}
+
+fn tree_size<K, V>(tree: &BTreeMap<K, V>) -> usize
+where
+    K: EstimateSize,
+    V: EstimateSize,
+{
+    tree.iter()
+        .map(|(key, value)| size::entry_size(key, value))
+        .sum()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::pages::Page;
+    use std::env;
+    use std::process;
 
     #[test]
     fn from_key_value_must_create_page() {
@@ -172,12 +381,34 @@ mod tests {
         let mut page: Page<_, usize> = Page::from_range(10, 20);
 
         assert_eq!(0, page.size());
-        assert_eq!(true, page.insert(15, 150));
+        assert_eq!(None, page.insert(15, 150));
         assert_eq!(1, page.size());
-        assert_eq!(false, page.insert(15, 150));
+        assert_eq!(Some(150), page.insert(15, 150));
         assert_eq!(1, page.size());
     }
 
+    #[test]
+    fn bytes_must_follow_page_entries() {
+        let mut page: Page<u32, Vec<u8>> = Page::from_range(0, 10);
+        let entry_size = |length: usize| 4 + 24 + length;
+
+        page.insert(1, vec![0; 10]);
+        page.insert(2, vec![0; 20]);
+        page.insert(1, vec![0; 5]);
+
+        assert_eq!(entry_size(5) + entry_size(20), page.bytes());
+
+        let next = page.split();
+
+        assert_eq!(entry_size(5), page.bytes());
+        assert_eq!(entry_size(20), next.bytes());
+
+        page.merge(next);
+        page.remove(&2);
+
+        assert_eq!(entry_size(5), page.bytes());
+    }
+
     #[test]
     fn get_must_return_value_if_exists() {
         let mut page: Page<_, usize> = Page::from_range(10, 20);
@@ -216,7 +447,7 @@ mod tests {
 
         page.insert(15, 150);
         assert_eq!(1, page.size());
-        assert_eq!(true, page.remove(&15));
+        assert_eq!(Some(150), page.remove(&15));
         assert_eq!(0, page.size());
     }
 
@@ -226,7 +457,7 @@ mod tests {
 
         page.insert(15, 150);
         assert_eq!(1, page.size());
-        assert_eq!(false, page.remove(&16));
+        assert_eq!(None, page.remove(&16));
         assert_eq!(1, page.size());
     }
 
@@ -348,4 +579,40 @@ mod tests {
 
         assert_eq!(None, page.succ(&2));
     }
+
+    #[test]
+    fn swap_must_keep_values() {
+        let path = env::temp_dir().join(format!("htdb-page-swap-{}", process::id()));
+        let mut page: Page<_, usize> = Page::from_range(1, 2);
+
+        page.insert(1, 10);
+        page.insert(2, 20);
+        page.swap_out(&path).unwrap();
+
+        assert_eq!(false, page.is_resident());
+        assert_eq!(2, page.size());
+        assert_eq!(None, page.get(&1));
+
+        page.swap_in().unwrap();
+
+        assert_eq!(true, page.is_resident());
+        assert_eq!(Some(&10), page.get(&1));
+        assert_eq!(Some(&20), page.get(&2));
+    }
+
+    #[test]
+    fn merge_must_join_pages() {
+        let mut page: Page<_, usize> = Page::from_range(1, 2);
+        let mut next: Page<_, usize> = Page::from_range(3, 5);
+
+        page.insert(1, 10);
+        next.insert(3, 30);
+        next.insert(4, 40);
+        page.merge(next);
+
+        assert_eq!(1, *page.range_start());
+        assert_eq!(5, *page.range_end());
+        assert_eq!(3, page.size());
+        assert_eq!(Some(&40), page.get(&4));
+    }
 }
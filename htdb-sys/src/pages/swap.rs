@@ -0,0 +1,70 @@
+use crate::DatabaseError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+static NEXT_SWAP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Page content stored on disk. The file is removed when the swap file is dropped.
+#[derive(Debug)]
+pub struct SwapFile {
+    path: PathBuf,
+    size: usize,
+}
+
+impl SwapFile {
+    pub fn write<K, V>(directory: &Path, tree: &BTreeMap<K, V>) -> Result<SwapFile, DatabaseError>
+    where
+        K: Ord + Serialize,
+        V: Serialize,
+    {
+        let id = NEXT_SWAP_ID.fetch_add(1, Ordering::Relaxed);
+        let path = directory.join(format!("page-{}-{}.swap", process::id(), id));
+
+        fs::create_dir_all(directory).map_err(DatabaseError::create_file_error)?;
+
+        let file = File::create(&path).map_err(DatabaseError::create_file_error)?;
+        let swap = SwapFile {
+            path,
+            size: tree.len(),
+        };
+        let mut writer = BufWriter::new(file);
+
+        bincode::serialize_into(&mut writer, tree).map_err(DatabaseError::serialize_error)?;
+        writer
+            .into_inner()
+            .map_err(|error| DatabaseError::create_file_error(error.into_error()))?;
+
+        Ok(swap)
+    }
+
+    pub fn read<K, V>(&self) -> Result<BTreeMap<K, V>, DatabaseError>
+    where
+        K: Ord + DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let file = File::open(&self.path).map_err(DatabaseError::open_file_error)?;
+        let reader = BufReader::new(file);
+
+        bincode::deserialize_from(reader).map_err(DatabaseError::serialize_error)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for SwapFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,34 @@
+/// Key which can be searched by prefix. Keys starting with the same prefix must be adjacent in
+/// key order.
+pub trait PrefixKey: Ord {
+    type Prefix: ?Sized;
+
+    /// Returns the smallest key starting with `prefix`.
+    fn prefix_start(prefix: &Self::Prefix) -> Self;
+
+    fn has_prefix(&self, prefix: &Self::Prefix) -> bool;
+}
+
+impl PrefixKey for Vec<u8> {
+    type Prefix = [u8];
+
+    fn prefix_start(prefix: &[u8]) -> Self {
+        prefix.to_vec()
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> bool {
+        self.starts_with(prefix)
+    }
+}
+
+impl PrefixKey for String {
+    type Prefix = str;
+
+    fn prefix_start(prefix: &str) -> Self {
+        prefix.to_string()
+    }
+
+    fn has_prefix(&self, prefix: &str) -> bool {
+        self.starts_with(prefix)
+    }
+}
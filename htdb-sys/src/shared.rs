@@ -0,0 +1,1499 @@
+use crate::config::Config;
+use crate::config::LoadStatus;
+use crate::cursor::RangePage;
+use crate::database;
+use crate::database::Database;
+use crate::expiry;
+use crate::pages::Pages;
+use crate::pages::Residency;
+use crate::stats::Stats;
+use crate::transaction::SharedTransaction;
+use crate::transaction::Undo;
+use crate::transaction::Writes;
+use crate::visiter::TreeVisiter;
+use crate::wal::LogRecord;
+use crate::wal::WriteAheadLog;
+use crate::Bincode;
+use crate::Codec;
+use crate::DatabaseError;
+use crate::EstimateSize;
+use crate::HashTreeVisiter;
+use crate::MergeOperator;
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::Serialize;
+use serde::Serializer;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::RwLockWriteGuard;
+use std::sync::Weak;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const SHARDS: usize = 16;
+/// Odd multiplier close to 2^64 divided by golden ratio, spreads hash bits over high bits.
+const SHARD_MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+
+type ReadGuards<'a, H, K, V, S> = Vec<RwLockReadGuard<'a, Database<H, K, V, S>>>;
+type WriteGuards<'a, H, K, V, S> = Vec<RwLockWriteGuard<'a, Database<H, K, V, S>>>;
+
+/// Database which can be shared between threads. Partitions are distributed between shards by
+/// hash key, every shard has its own lock. Reads proceed concurrently while all required pages
+/// are in memory, writes to partitions in different shards proceed in parallel. Operations on
+/// the whole database lock all shards. Shards are selected and partitions are kept by hashers
+/// built by `S`, same as in `Database`.
+#[derive(Debug)]
+pub struct SharedDatabase<H, K, V, S = RandomState, C = Bincode>
+where
+    K: Ord,
+{
+    inner: Arc<Inner<H, K, V, S, C>>,
+}
+
+#[derive(Debug)]
+struct Inner<H, K, V, S, C>
+where
+    K: Ord,
+{
+    config: Arc<Config>,
+    hash_builder: S,
+    /// Codec of snapshots, shards never save snapshots by themselves.
+    codec: C,
+    shards: Vec<RwLock<Database<H, K, V, S>>>,
+    /// Resident pages of all shards, so `Config::max_pages` limits the whole database.
+    residency: Arc<Residency>,
+    log: Mutex<Option<WriteAheadLog>>,
+    last_save: Mutex<Option<Duration>>,
+    last_load: Mutex<Option<Duration>>,
+}
+
+impl<H, K, V> SharedDatabase<H, K, V>
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Ord
+        + Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    V: Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+{
+    pub fn new(config: Config) -> SharedDatabase<H, K, V> {
+        SharedDatabase::with_hasher(config, RandomState::new())
+    }
+
+    /// Creates database and, if write-ahead log is enabled, restores its state from the last
+    /// snapshot and the log.
+    pub fn open(config: Config) -> Result<SharedDatabase<H, K, V>, DatabaseError> {
+        SharedDatabase::open_with_hasher(config, RandomState::new())
+    }
+}
+
+impl<H, K, V, S> SharedDatabase<H, K, V, S>
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Ord
+        + Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    V: Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Creates database which hashes partition keys with hashers built by `hash_builder`.
+    pub fn with_hasher(config: Config, hash_builder: S) -> SharedDatabase<H, K, V, S> {
+        SharedDatabase::with_codec(config, hash_builder, Bincode)
+    }
+
+    /// Same as `open` but hashes partition keys with hashers built by `hash_builder`.
+    pub fn open_with_hasher(
+        config: Config,
+        hash_builder: S,
+    ) -> Result<SharedDatabase<H, K, V, S>, DatabaseError> {
+        SharedDatabase::open_with_codec(config, hash_builder, Bincode)
+    }
+}
+
+impl<H, K, V, S, C> SharedDatabase<H, K, V, S, C>
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Ord
+        + Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    V: Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+    C: Codec + Send + Sync + 'static,
+{
+    /// Creates database which encodes snapshots with `codec`, same as `Database::with_codec`.
+    pub fn with_codec(config: Config, hash_builder: S, codec: C) -> SharedDatabase<H, K, V, S, C> {
+        // Shards are never logged nor swept by themselves, log and sweep are shared between them.
+        let shard_config = Arc::new(
+            config
+                .clone()
+                .set_write_ahead_log(false)
+                .set_expiry_sweep_interval(None),
+        );
+        let residency = Arc::new(Residency::default());
+        let shards = (0..SHARDS)
+            .map(|_| {
+                RwLock::new(Database::with_residency(
+                    shard_config.clone(),
+                    hash_builder.clone(),
+                    residency.clone(),
+                ))
+            })
+            .collect();
+
+        let inner = Arc::new(Inner {
+            config: Arc::new(config),
+            hash_builder,
+            codec,
+            shards,
+            residency,
+            log: Mutex::new(None),
+            last_save: Mutex::new(None),
+            last_load: Mutex::new(None),
+        });
+
+        if let Some(interval) = inner.config.expiry_sweep_interval() {
+            let inner = Arc::downgrade(&inner);
+
+            thread::spawn(move || sweep_expired(inner, interval));
+        }
+
+        SharedDatabase { inner }
+    }
+
+    /// Same as `open` but encodes snapshots with `codec`.
+    pub fn open_with_codec(
+        config: Config,
+        hash_builder: S,
+        codec: C,
+    ) -> Result<SharedDatabase<H, K, V, S, C>, DatabaseError> {
+        let database = SharedDatabase::with_codec(config, hash_builder, codec);
+
+        if database.inner.config.write_ahead_log() {
+            database.load()?;
+        }
+
+        Ok(database)
+    }
+
+    pub fn get(&self, hash_key: &H, tree_key: &K) -> Result<Option<V>, DatabaseError> {
+        if let Some(value) = self.read_shard(hash_key)?.try_get(hash_key, tree_key) {
+            return Ok(value.cloned());
+        }
+
+        Ok(self
+            .write_shard(hash_key)?
+            .get(hash_key, tree_key)?
+            .cloned())
+    }
+
+    pub fn put(&self, hash_key: H, tree_key: K, data: V) -> Result<bool, DatabaseError> {
+        let mut shard = self.write_shard(&hash_key)?;
+
+        self.append_log(&LogRecord::Put {
+            hash_key: &hash_key,
+            tree_key: &tree_key,
+            data: &data,
+        })?;
+
+        shard.put(hash_key, tree_key, data)
+    }
+
+    pub fn put_with_ttl(
+        &self,
+        hash_key: H,
+        tree_key: K,
+        data: V,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError> {
+        let expires_at = expiry::expires_at(ttl);
+        let mut shard = self.write_shard(&hash_key)?;
+
+        self.append_log(&LogRecord::PutWithExpiry {
+            hash_key: &hash_key,
+            tree_key: &tree_key,
+            data: &data,
+            expires_at,
+        })?;
+
+        shard.put_with_expiry(hash_key, tree_key, data, expires_at)
+    }
+
+    pub fn contains(&self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        if let Some(result) = self.read_shard(hash_key)?.try_contains(hash_key, tree_key) {
+            return Ok(result);
+        }
+
+        self.write_shard(hash_key)?.contains(hash_key, tree_key)
+    }
+
+    pub fn delete(&self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        let mut shard = self.write_shard(hash_key)?;
+
+        self.append_log(&LogRecord::<_, _, &V>::Delete { hash_key, tree_key })?;
+
+        shard.delete(hash_key, tree_key)
+    }
+
+    /// Puts value only if partition does not contain `tree_key`. Returns `true` if value was put.
+    pub fn put_if_absent(&self, hash_key: H, tree_key: K, data: V) -> Result<bool, DatabaseError> {
+        let mut shard = self.write_shard(&hash_key)?;
+
+        if shard.contains(&hash_key, &tree_key)? {
+            return Ok(false);
+        }
+
+        self.append_log(&LogRecord::Put {
+            hash_key: &hash_key,
+            tree_key: &tree_key,
+            data: &data,
+        })?;
+
+        shard.put(hash_key, tree_key, data)
+    }
+
+    /// Replaces value only if partition contains `tree_key`. Returns `true` if value was replaced.
+    pub fn replace_if_present(
+        &self,
+        hash_key: H,
+        tree_key: K,
+        data: V,
+    ) -> Result<bool, DatabaseError> {
+        let mut shard = self.write_shard(&hash_key)?;
+
+        if !shard.contains(&hash_key, &tree_key)? {
+            return Ok(false);
+        }
+
+        self.append_log(&LogRecord::Put {
+            hash_key: &hash_key,
+            tree_key: &tree_key,
+            data: &data,
+        })?;
+
+        shard.put(hash_key, tree_key, data).map(|_| true)
+    }
+
+    /// Sets value to `new` only if current value equals to `expected`, `None` means missing value
+    /// in both cases. Shard is locked during comparison and change, so concurrent writers can use
+    /// it for optimistic locking.
+    pub fn compare_and_swap(
+        &self,
+        hash_key: H,
+        tree_key: K,
+        expected: Option<&V>,
+        new: Option<V>,
+    ) -> Result<bool, DatabaseError>
+    where
+        V: PartialEq,
+    {
+        let mut shard = self.write_shard(&hash_key)?;
+
+        if shard.get(&hash_key, &tree_key)? != expected {
+            return Ok(false);
+        }
+
+        match new {
+            Some(data) => {
+                self.append_log(&LogRecord::Put {
+                    hash_key: &hash_key,
+                    tree_key: &tree_key,
+                    data: &data,
+                })?;
+                shard.put(hash_key, tree_key, data)?;
+            }
+            None => {
+                self.append_log(&LogRecord::<_, _, &V>::Delete {
+                    hash_key: &hash_key,
+                    tree_key: &tree_key,
+                })?;
+                shard.delete(&hash_key, &tree_key)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Sets operator used by `merge` in all shards, replacing previous one.
+    pub fn set_merge_operator<M>(&self, operator: M) -> Result<(), DatabaseError>
+    where
+        M: MergeOperator<V> + 'static,
+    {
+        let operator: Arc<dyn MergeOperator<V>> = Arc::new(operator);
+
+        for mut shard in self.write_all()? {
+            shard.set_shared_merge_operator(operator.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Returns at most `limit` entries of partition within `range` in key order and cursor which
+    /// resumes right after the last returned entry. Shard is locked for writing only if swapped
+    /// out page must be loaded.
+    pub fn range_page<R>(
+        &self,
+        hash_key: &H,
+        range: R,
+        limit: usize,
+    ) -> Result<RangePage<K, V>, DatabaseError>
+    where
+        R: RangeBounds<K>,
+    {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+
+        if let Some(result) =
+            self.read_shard(hash_key)?
+                .try_range_page(hash_key, bounds.clone(), limit)
+        {
+            return result;
+        }
+
+        self.write_shard(hash_key)?
+            .range_page(hash_key, bounds, limit)
+    }
+
+    /// Deletes all values of partition within `range`. Returns number of deleted values.
+    pub fn delete_range<R>(&self, hash_key: &H, range: R) -> Result<usize, DatabaseError>
+    where
+        R: RangeBounds<K>,
+    {
+        let mut shard = self.write_shard(hash_key)?;
+
+        if !shard.contains_partition(hash_key) {
+            return Ok(0);
+        }
+
+        self.append_log(&LogRecord::<_, _, &V>::DeleteRange {
+            hash_key,
+            range_start: range.start_bound(),
+            range_end: range.end_bound(),
+        })?;
+
+        shard.delete_range(hash_key, range)
+    }
+
+    pub fn range<F>(
+        &self,
+        hash_key: &H,
+        tree_first: &K,
+        tree_last: &K,
+        mut callback: F,
+    ) -> Result<(), DatabaseError>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        if let Some(result) =
+            self.read_shard(hash_key)?
+                .try_range(hash_key, tree_first, tree_last, &mut callback)
+        {
+            return result;
+        }
+
+        self.write_shard(hash_key)?
+            .range(hash_key, tree_first, tree_last, callback)
+    }
+
+    pub fn succ(&self, hash_key: &H, tree_key: &K) -> Result<Option<(K, V)>, DatabaseError> {
+        if let Some(entry) = self.read_shard(hash_key)?.try_succ(hash_key, tree_key) {
+            return Ok(entry.map(|(key, value)| (key.clone(), value.clone())));
+        }
+
+        Ok(self
+            .write_shard(hash_key)?
+            .succ(hash_key, tree_key)?
+            .map(|(key, value)| (key.clone(), value.clone())))
+    }
+
+    pub fn pred(&self, hash_key: &H, tree_key: &K) -> Result<Option<(K, V)>, DatabaseError> {
+        if let Some(entry) = self.read_shard(hash_key)?.try_pred(hash_key, tree_key) {
+            return Ok(entry.map(|(key, value)| (key.clone(), value.clone())));
+        }
+
+        Ok(self
+            .write_shard(hash_key)?
+            .pred(hash_key, tree_key)?
+            .map(|(key, value)| (key.clone(), value.clone())))
+    }
+
+    /// Returns hash keys of all non-empty partitions in arbitrary order.
+    pub fn partitions(&self) -> Result<Vec<H>, DatabaseError>
+    where
+        H: Clone,
+    {
+        let shards = self.read_all()?;
+
+        Ok(shards
+            .iter()
+            .flat_map(|shard| shard.partitions().cloned())
+            .collect())
+    }
+
+    pub fn contains_partition(&self, hash_key: &H) -> Result<bool, DatabaseError> {
+        Ok(self.read_shard(hash_key)?.contains_partition(hash_key))
+    }
+
+    pub fn partition_len(&self, hash_key: &H) -> Result<usize, DatabaseError> {
+        Ok(self.read_shard(hash_key)?.partition_len(hash_key))
+    }
+
+    /// Returns number of entries in partition which are not expired, see
+    /// `Database::live_partition_len`.
+    pub fn live_partition_len(&self, hash_key: &H) -> Result<usize, DatabaseError> {
+        Ok(self.read_shard(hash_key)?.live_partition_len(hash_key))
+    }
+
+    pub fn drop_partition(&self, hash_key: &H) -> Result<bool, DatabaseError> {
+        let mut shard = self.write_shard(hash_key)?;
+
+        if !shard.contains_partition(hash_key) {
+            return Ok(false);
+        }
+
+        self.append_log(&LogRecord::<_, &K, &V>::DropPartition { hash_key })?;
+
+        shard.drop_partition(hash_key)
+    }
+
+    /// Moves all entries of partition to `new_hash_key`, partition with this key is replaced.
+    /// If partitions belong to different shards, both shards are locked.
+    pub fn rename_partition(&self, hash_key: &H, new_hash_key: H) -> Result<bool, DatabaseError> {
+        let source_index = self.shard_index(hash_key);
+        let target_index = self.shard_index(&new_hash_key);
+
+        // Shards are locked in index order, same as in `read_all` and `write_all`.
+        let (mut source, target) = match source_index.cmp(&target_index) {
+            Ordering::Less => {
+                let source = self.write_shard_at(source_index)?;
+
+                (source, Some(self.write_shard_at(target_index)?))
+            }
+            Ordering::Equal => (self.write_shard_at(source_index)?, None),
+            Ordering::Greater => {
+                let target = self.write_shard_at(target_index)?;
+
+                (self.write_shard_at(source_index)?, Some(target))
+            }
+        };
+
+        if !source.contains_partition(hash_key) {
+            return Ok(false);
+        }
+
+        self.append_log(&LogRecord::<_, &K, &V>::RenamePartition {
+            hash_key,
+            new_hash_key: &new_hash_key,
+        })?;
+
+        match target {
+            Some(mut target) => {
+                if let Some(pages) = source.partition_map_mut().remove(hash_key) {
+                    target.insert_partition(new_hash_key, pages);
+                }
+
+                Ok(true)
+            }
+            None => source.rename_partition(hash_key, new_hash_key),
+        }
+    }
+
+    pub fn count(&self) -> Result<usize, DatabaseError> {
+        let shards = self.read_all()?;
+        let mut count = 0;
+
+        for shard in &shards {
+            count += shard.count()?;
+        }
+
+        Ok(count)
+    }
+
+    /// Returns number of entries in all shards which are not expired, see `Database::live_count`.
+    pub fn live_count(&self) -> Result<usize, DatabaseError> {
+        let shards = self.read_all()?;
+        let mut count = 0;
+
+        for shard in &shards {
+            count += shard.live_count()?;
+        }
+
+        Ok(count)
+    }
+
+    /// Removes expired entries from all shards, shards are locked one by one.
+    pub fn remove_expired(&self) -> Result<usize, DatabaseError> {
+        let mut removed = 0;
+
+        for index in 0..self.inner.shards.len() {
+            removed += self.write_shard_at(index)?.remove_expired()?;
+        }
+
+        Ok(removed)
+    }
+
+    pub fn compact(&self) -> Result<(), DatabaseError> {
+        for shard in &self.inner.shards {
+            shard
+                .write()
+                .map_err(DatabaseError::lock_error)?
+                .compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes snapshot of all shards, writers are blocked until snapshot is saved.
+    pub fn save(&self) -> Result<(), DatabaseError> {
+        let start = Instant::now();
+        let shards = self.read_all()?;
+        let mut log = self.lock_log()?;
+        let mut entries = 0;
+
+        // Log must be checked before snapshot replaces records it may contain.
+        Database::<H, K, V>::open_log(&self.inner.config, &mut log)?;
+
+        for shard in &shards {
+            entries += shard.count()?;
+        }
+
+        Database::<H, K, V, S, C>::save_snapshot(
+            &self.inner.config,
+            &self.inner.codec,
+            &Shards(&shards),
+            entries,
+        )?;
+
+        if let Some(log) = log.as_mut() {
+            log.truncate()?;
+        }
+
+        *self
+            .inner
+            .last_save
+            .lock()
+            .map_err(DatabaseError::lock_error)? = Some(start.elapsed());
+
+        Ok(())
+    }
+
+    /// Same as `Database::load`, shards are left unchanged if snapshot can not be read.
+    pub fn load(&self) -> Result<LoadStatus, DatabaseError> {
+        let start = Instant::now();
+        let mut shards = self.write_all()?;
+        let mut log = self.lock_log()?;
+        let snapshot =
+            Database::<H, K, V, S, C>::load_snapshot(&self.inner.config, &self.inner.codec)?;
+        let mut status = LoadStatus::Loaded;
+
+        *log = None;
+
+        for shard in shards.iter_mut() {
+            shard.partition_map_mut().clear();
+        }
+
+        if let Some((data, snapshot_status)) = snapshot {
+            for (hash_key, pages) in data {
+                let index = self.shard_index(&hash_key);
+
+                shards[index].insert_partition(hash_key, pages);
+            }
+
+            status = snapshot_status;
+        }
+
+        if self.inner.config.write_ahead_log() {
+            let path = database::log_path(&self.inner.config);
+
+            let replayed = WriteAheadLog::open(&path, |record| self.replay(&mut shards, record))?;
+
+            *log = Some(replayed.set_sync(self.inner.config.sync_write_ahead_log()));
+        }
+
+        *self
+            .inner
+            .last_load
+            .lock()
+            .map_err(DatabaseError::lock_error)? = Some(start.elapsed());
+
+        Ok(status)
+    }
+
+    /// Same as `Database::stats`, statistics of all shards are combined.
+    pub fn stats(&self) -> Result<Stats, DatabaseError> {
+        let mut stats = Stats::default();
+
+        for shard in &self.read_all()? {
+            stats.merge(&shard.stats()?);
+        }
+
+        let last_save = *self
+            .inner
+            .last_save
+            .lock()
+            .map_err(DatabaseError::lock_error)?;
+        let last_load = *self
+            .inner
+            .last_load
+            .lock()
+            .map_err(DatabaseError::lock_error)?;
+
+        stats.set_durations(last_save, last_load);
+
+        Ok(stats)
+    }
+
+    pub fn visit<T>(&self, visiter: &mut T) -> Result<(), DatabaseError>
+    where
+        T: HashTreeVisiter<H, K, V> + TreeVisiter<K, V>,
+    {
+        for shard in &self.read_all()? {
+            shard.visit(visiter)?;
+        }
+
+        Ok(())
+    }
+
+    fn append_log(&self, record: &LogRecord<&H, &K, &V>) -> Result<(), DatabaseError> {
+        if !self.inner.config.write_ahead_log() {
+            return Ok(());
+        }
+
+        let mut log = self.lock_log()?;
+
+        Database::<H, K, V>::open_log(&self.inner.config, &mut log)?;
+
+        match log.as_mut() {
+            Some(log) => log.append(record),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies log record to shard owning its partition, batch records are split between shards
+    /// and renamed partitions are moved to shard of their new hash key.
+    fn replay(
+        &self,
+        shards: &mut WriteGuards<'_, H, K, V, S>,
+        record: LogRecord<H, K, V>,
+    ) -> Result<(), DatabaseError> {
+        match record {
+            LogRecord::Batch(records) => records
+                .into_iter()
+                .try_for_each(|record| self.replay(shards, record)),
+            LogRecord::RenamePartition {
+                hash_key,
+                new_hash_key,
+            } => {
+                let source = self.shard_index(&hash_key);
+                let target = self.shard_index(&new_hash_key);
+
+                if let Some(pages) = shards[source].partition_map_mut().remove(&hash_key) {
+                    shards[target].insert_partition(new_hash_key, pages);
+                }
+
+                Ok(())
+            }
+            record => match record.hash_key().map(|hash_key| self.shard_index(hash_key)) {
+                Some(index) => shards[index].apply(record),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Shards use the same hasher for their partitions, so hash is mixed before shard is taken
+    /// from its high bits. Otherwise partitions of every shard would share low bits of hash and
+    /// crowd into small part of shard table.
+    fn shard_index(&self, hash_key: &H) -> usize {
+        let hash = self.inner.hash_builder.hash_one(hash_key);
+        let mixed = hash.wrapping_mul(SHARD_MIX) >> 32;
+
+        (mixed % self.inner.shards.len() as u64) as usize
+    }
+
+    fn read_shard(
+        &self,
+        hash_key: &H,
+    ) -> Result<RwLockReadGuard<'_, Database<H, K, V, S>>, DatabaseError> {
+        self.inner.shards[self.shard_index(hash_key)]
+            .read()
+            .map_err(DatabaseError::lock_error)
+    }
+
+    fn write_shard(
+        &self,
+        hash_key: &H,
+    ) -> Result<RwLockWriteGuard<'_, Database<H, K, V, S>>, DatabaseError> {
+        self.write_shard_at(self.shard_index(hash_key))
+    }
+
+    fn write_shard_at(
+        &self,
+        index: usize,
+    ) -> Result<RwLockWriteGuard<'_, Database<H, K, V, S>>, DatabaseError> {
+        self.reclaim(index)?;
+        self.inner.shards[index]
+            .write()
+            .map_err(DatabaseError::lock_error)
+    }
+
+    /// Swaps out pages of shards other than `index` while resident pages of all shards exceed
+    /// `Config::max_pages`. Shards locked by other threads are skipped instead of waited for, so
+    /// shards are never locked out of order.
+    fn reclaim(&self, index: usize) -> Result<(), DatabaseError> {
+        let exceeds = || self.inner.residency.exceeds(&self.inner.config);
+
+        if !exceeds() {
+            return Ok(());
+        }
+
+        for (other, shard) in self.inner.shards.iter().enumerate() {
+            if other == index {
+                continue;
+            }
+
+            if let Ok(mut shard) = shard.try_write() {
+                shard.reclaim(None)?;
+            }
+
+            if !exceeds() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Locks all shards for reading, shards are always locked in the same order.
+    fn read_all(&self) -> Result<ReadGuards<'_, H, K, V, S>, DatabaseError> {
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| shard.read().map_err(DatabaseError::lock_error))
+            .collect()
+    }
+
+    /// Locks all shards for writing, shards are always locked in the same order.
+    fn write_all(&self) -> Result<WriteGuards<'_, H, K, V, S>, DatabaseError> {
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| shard.write().map_err(DatabaseError::lock_error))
+            .collect()
+    }
+
+    fn lock_log(&self) -> Result<MutexGuard<'_, Option<WriteAheadLog>>, DatabaseError> {
+        self.inner.log.lock().map_err(DatabaseError::lock_error)
+    }
+}
+
+impl<H, K, V, S, C> SharedDatabase<H, K, V, S, C>
+where
+    H: Eq + Hash + Clone + Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Ord
+        + Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    V: Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+    C: Codec + Send + Sync + 'static,
+{
+    /// Starts transaction. Changes made in transaction are invisible until it is committed and
+    /// discarded if transaction is rolled back or dropped.
+    pub fn begin(&self) -> SharedTransaction<H, K, V, S, C> {
+        SharedTransaction::new(self.clone())
+    }
+
+    /// Passes current value to `f` and stores value left in the option after the call. Shard is
+    /// locked during the call, so `f` must not access the same database. Expiry time of changed
+    /// value is kept, changed value is written to write-ahead log before it is applied.
+    pub fn update<F, T>(&self, hash_key: H, tree_key: K, f: F) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(&mut Option<V>) -> T,
+        V: PartialEq,
+    {
+        let mut shard = self.write_shard(&hash_key)?;
+
+        self.update_shard(&mut shard, hash_key, tree_key, f)
+    }
+
+    /// Combines current value with `operand` using operator set by `set_merge_operator`. Returns
+    /// merged value.
+    pub fn merge(&self, hash_key: H, tree_key: K, operand: V) -> Result<Option<V>, DatabaseError>
+    where
+        V: PartialEq,
+    {
+        let mut shard = self.write_shard(&hash_key)?;
+        let operator = shard
+            .merge_operator()
+            .ok_or_else(|| DatabaseError::merge_error("Merge operator is not set"))?;
+
+        self.merge_shard(&mut shard, hash_key, tree_key, operand, operator.as_ref())
+    }
+
+    /// Combines current value with `operand` using given operator. Returns merged value.
+    pub fn merge_with<M>(
+        &self,
+        hash_key: H,
+        tree_key: K,
+        operand: V,
+        operator: &M,
+    ) -> Result<Option<V>, DatabaseError>
+    where
+        M: MergeOperator<V> + ?Sized,
+        V: PartialEq,
+    {
+        let mut shard = self.write_shard(&hash_key)?;
+
+        self.merge_shard(&mut shard, hash_key, tree_key, operand, operator)
+    }
+
+    /// Applies all transaction changes while shards of all changed partitions are locked, so
+    /// other threads see either none or all of them. Changes are written to write-ahead log as
+    /// single record. If any change can not be applied, already applied changes are reverted.
+    pub(crate) fn commit(&self, writes: Writes<H, K, V>) -> Result<(), DatabaseError> {
+        let mut groups: BTreeMap<usize, Writes<H, K, V>> = BTreeMap::new();
+
+        for (hash_key, tree) in writes {
+            groups
+                .entry(self.shard_index(&hash_key))
+                .or_default()
+                .insert(hash_key, tree);
+        }
+
+        // Shards are locked in ascending order, the same order as `write_all` uses.
+        let mut shards = groups
+            .keys()
+            .map(|&index| self.write_shard_at(index))
+            .collect::<Result<WriteGuards<'_, H, K, V, S>, _>>()?;
+        let records = groups
+            .values()
+            .flatten()
+            .flat_map(|(hash_key, tree)| {
+                tree.iter().map(move |(tree_key, data)| {
+                    Database::<H, K, V>::log_record(hash_key, tree_key, data.as_ref())
+                })
+            })
+            .collect();
+
+        self.append_log(&LogRecord::Batch(records))?;
+
+        let mut undo = Vec::new();
+
+        for (shard, writes) in shards.iter_mut().zip(groups.into_values()) {
+            let mut shard_undo = Vec::new();
+            let result = shard.apply_writes(writes, &mut shard_undo);
+
+            undo.push(shard_undo);
+
+            if let Err(error) = result {
+                return match self.revert(&mut shards, undo) {
+                    Ok(()) => Err(error),
+                    Err(revert_error) => Err(DatabaseError::revert_error(error, revert_error)),
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates value in locked shard, changed value is logged to the shared log.
+    fn update_shard<F, T>(
+        &self,
+        shard: &mut Database<H, K, V, S>,
+        hash_key: H,
+        tree_key: K,
+        f: F,
+    ) -> Result<T, DatabaseError>
+    where
+        F: FnOnce(&mut Option<V>) -> T,
+        V: PartialEq,
+    {
+        if !self.inner.config.write_ahead_log() {
+            return shard.update(hash_key, tree_key, f);
+        }
+
+        shard.update_with_log(hash_key, tree_key, f, |record| self.append_log(record))
+    }
+
+    /// Merges value in locked shard. Merge is applied as update, so expiry time of value is kept.
+    fn merge_shard<M>(
+        &self,
+        shard: &mut Database<H, K, V, S>,
+        hash_key: H,
+        tree_key: K,
+        operand: V,
+        operator: &M,
+    ) -> Result<Option<V>, DatabaseError>
+    where
+        M: MergeOperator<V> + ?Sized,
+        V: PartialEq,
+    {
+        self.update_shard(shard, hash_key, tree_key, |value| {
+            operator.merge(value, operand).map(|_| value.clone())
+        })?
+    }
+
+    /// Restores values replaced in locked shards, `undo` holds changes applied to each shard.
+    /// Every value is restored even if some of them fail, the first error is returned.
+    fn revert(
+        &self,
+        shards: &mut WriteGuards<'_, H, K, V, S>,
+        undo: Vec<Undo<H, K, V>>,
+    ) -> Result<(), DatabaseError> {
+        let records = undo
+            .iter()
+            .rev()
+            .flat_map(Database::<H, K, V>::undo_records)
+            .collect();
+        let mut result = self.append_log(&LogRecord::Batch(records));
+
+        for (shard, undo) in shards.iter_mut().zip(undo).rev() {
+            if let Err(error) = shard.revert(undo) {
+                result = result.and(Err(error));
+            }
+        }
+
+        result
+    }
+}
+
+impl<H, K, V, S, C> Clone for SharedDatabase<H, K, V, S, C>
+where
+    K: Ord,
+{
+    fn clone(&self) -> Self {
+        SharedDatabase {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// Periodically removes expired entries until all handles of database are dropped.
+fn sweep_expired<H, K, V, S, C>(inner: Weak<Inner<H, K, V, S, C>>, interval: Duration)
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    S: BuildHasher,
+{
+    loop {
+        thread::sleep(interval);
+
+        let inner = match inner.upgrade() {
+            Some(inner) => inner,
+            None => break,
+        };
+
+        for shard in &inner.shards {
+            // Failed sweep only keeps invisible entries in memory, the same error is returned to
+            // the next caller which loads affected page.
+            if let Ok(mut shard) = shard.write() {
+                shard.remove_expired().ok();
+            }
+        }
+    }
+}
+
+/// Serializes partitions of all shards as single map, snapshot has the same format as snapshot
+/// of `Database`.
+struct Shards<'a, 'b, H, K, V, S>(&'a [RwLockReadGuard<'b, Database<H, K, V, S>>])
+where
+    K: Ord;
+
+impl<'a, 'b, H, K, V, S> Serialize for Shards<'a, 'b, H, K, V, S>
+where
+    H: Eq + Hash + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    S: BuildHasher,
+{
+    fn serialize<R>(&self, serializer: R) -> Result<R::Ok, R::Error>
+    where
+        R: Serializer,
+    {
+        let length = self.0.iter().map(|shard| shard.partition_map().len()).sum();
+        let mut map = serializer.serialize_map(Some(length))?;
+
+        for shard in self.0 {
+            for (hash_key, pages) in shard.partition_map() {
+                map.serialize_entry(hash_key, pages as &Pages<K, V>)?;
+            }
+        }
+
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Cbor;
+    use crate::Codec;
+    use crate::Config;
+    use crate::Database;
+    use crate::IntegerAdd;
+    use crate::SharedDatabase;
+    use crate::TrivialHasherBuilder;
+    use std::collections::hash_map::RandomState;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn databases_must_be_send_and_sync() {
+        assert_send_sync::<Database<u32, u32, u32>>();
+        assert_send_sync::<SharedDatabase<u32, u32, u32>>();
+    }
+
+    #[test]
+    fn with_hasher_must_spread_partitions_between_shards() {
+        let database: SharedDatabase<u32, u32, u32, TrivialHasherBuilder> =
+            SharedDatabase::with_hasher(Config::default(), TrivialHasherBuilder::default());
+
+        for hash_key in 0..64 {
+            database.put(hash_key, 1, hash_key).unwrap();
+        }
+
+        for hash_key in 0..64 {
+            assert_eq!(Some(hash_key), database.get(&hash_key, &1).unwrap());
+        }
+
+        let used = database
+            .inner
+            .shards
+            .iter()
+            .filter(|shard| shard.read().unwrap().partitions().next().is_some())
+            .count();
+
+        assert_eq!(true, used > 8);
+        assert_eq!(64, database.partitions().unwrap().len());
+    }
+
+    #[test]
+    fn threads_must_see_all_writes() {
+        let config = Config::default().set_max_page_size(4);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let database = database.clone();
+
+                thread::spawn(move || {
+                    for index in 0..100 {
+                        database.put(thread, index, index * 10).unwrap();
+                        assert_eq!(Some(index * 10), database.get(&thread, &index).unwrap());
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(400, database.count().unwrap());
+        assert_eq!(Some((5, 50)), database.succ(&2, &4).unwrap());
+        assert_eq!(Some((3, 30)), database.pred(&2, &4).unwrap());
+    }
+
+    #[test]
+    fn get_must_load_swapped_pages() {
+        let path = env::temp_dir().join(format!("htdb-shared-{}", process::id()));
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(path);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+        let mut result = Vec::new();
+
+        for index in 0..10 {
+            database.put(1, index, index).unwrap();
+        }
+
+        for index in 0..10 {
+            assert_eq!(Some(index), database.get(&1, &index).unwrap());
+        }
+
+        database
+            .range(&1, &2, &5, |&key, _| {
+                result.push(key);
+
+                true
+            })
+            .unwrap();
+
+        assert_eq!(vec![2, 3, 4, 5], result);
+    }
+
+    #[test]
+    fn max_pages_must_limit_resident_pages_of_all_shards() {
+        let path = env::temp_dir().join(format!("htdb-shared-max-pages-{}", process::id()));
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(4))
+            .set_storage_path(path);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+
+        for hash_key in 0..20 {
+            for tree_key in 0..4 {
+                database.put(hash_key, tree_key, tree_key).unwrap();
+            }
+        }
+
+        // Every write may leave pages of its own partition above the limit until the next one.
+        assert_eq!(true, database.inner.residency.pages() <= 4 + 2);
+
+        for hash_key in 0..20 {
+            for tree_key in 0..4 {
+                assert_eq!(Some(tree_key), database.get(&hash_key, &tree_key).unwrap());
+            }
+        }
+
+        assert_eq!(true, database.inner.residency.pages() <= 4 + 2);
+    }
+
+    #[test]
+    fn range_page_must_load_swapped_pages() {
+        let path = env::temp_dir().join(format!("htdb-shared-range-page-{}", process::id()));
+        let config = Config::default()
+            .set_max_page_size(2)
+            .set_max_pages(Some(1))
+            .set_storage_path(path);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+        let mut keys = Vec::new();
+
+        for index in 0..10 {
+            database.put(1, index, index * 10).unwrap();
+        }
+
+        let (entries, mut cursor) = database.range_page(&1, 2..8, 3).unwrap();
+
+        keys.extend(entries.into_iter().map(|(key, _)| key));
+
+        while let Some(next) = cursor {
+            let (entries, next) = database.range_page(&1, next, 3).unwrap();
+
+            keys.extend(entries.into_iter().map(|(key, _)| key));
+            cursor = next;
+        }
+
+        assert_eq!(vec![2, 3, 4, 5, 6, 7], keys);
+        assert_eq!(
+            (vec![(9, 90)], None),
+            database.range_page(&1, 9.., 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn rename_partition_must_move_entries_between_shards() {
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(Config::default());
+
+        for index in 0..10 {
+            database.put(1, index, index).unwrap();
+        }
+
+        for hash in 2..20 {
+            assert_eq!(true, database.rename_partition(&(hash - 1), hash).unwrap());
+        }
+
+        assert_eq!(false, database.rename_partition(&1, 2).unwrap());
+        assert_eq!(vec![19], database.partitions().unwrap());
+        assert_eq!(10, database.partition_len(&19).unwrap());
+        assert_eq!(10, database.count().unwrap());
+        assert_eq!(Some(5), database.get(&19, &5).unwrap());
+        assert_eq!(true, database.drop_partition(&19).unwrap());
+        assert_eq!(0, database.count().unwrap());
+    }
+
+    #[test]
+    fn expired_entries_must_be_removed_in_background() {
+        let config = Config::default().set_expiry_sweep_interval(Some(Duration::from_millis(10)));
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+
+        for hash in 0..20 {
+            database
+                .put_with_ttl(hash, 1, hash, Duration::from_secs(0))
+                .unwrap();
+        }
+
+        database.put(1, 2, 2).unwrap();
+
+        assert_eq!(None, database.get(&1, &1).unwrap());
+
+        thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(1, database.count().unwrap());
+    }
+
+    #[test]
+    fn compare_and_swap_must_allow_single_winner() {
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(Config::default());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let database = database.clone();
+
+                thread::spawn(move || {
+                    let mut wins = 0;
+
+                    for round in 0..100 {
+                        let expected = if round == 0 { None } else { Some(round - 1) };
+
+                        loop {
+                            let current = database.get(&1, &1).unwrap();
+
+                            if current.is_some_and(|current| current >= round) {
+                                break;
+                            }
+
+                            if database
+                                .compare_and_swap(1, 1, expected.as_ref(), Some(round))
+                                .unwrap()
+                            {
+                                wins += 1;
+
+                                break;
+                            }
+                        }
+                    }
+
+                    wins
+                })
+            })
+            .collect();
+        let wins: u32 = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum();
+
+        assert_eq!(100, wins);
+        assert_eq!(Some(99), database.get(&1, &1).unwrap());
+    }
+
+    #[test]
+    fn stats_must_combine_all_shards() {
+        let config = Config::default().set_max_page_size(4);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+
+        for hash_key in 0..8 {
+            for tree_key in 0..=hash_key {
+                database.put(hash_key, tree_key, tree_key).unwrap();
+            }
+        }
+
+        let stats = database.stats().unwrap();
+
+        assert_eq!(8, stats.partitions());
+        assert_eq!(36, stats.entries());
+        assert_eq!((stats.pages() - 8) as u64, stats.splits());
+        assert_eq!(0.25, stats.min_page_fill());
+        assert_eq!(1.0, stats.max_page_fill());
+        assert_eq!(None, stats.last_save());
+    }
+
+    #[test]
+    fn merge_must_not_lose_concurrent_increments() {
+        let database: SharedDatabase<u32, u32, Vec<u8>> = SharedDatabase::new(Config::default());
+
+        database.set_merge_operator(IntegerAdd).unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let database = database.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        database.merge(1, 1, vec![1]).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(
+            Some(400i64.to_le_bytes().to_vec()),
+            database.get(&1, &1).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_must_restore_all_shards() {
+        let path = env::temp_dir().join(format!("htdb-shared-load-{}", process::id()));
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::open(config).unwrap();
+
+        for hash in 0..32 {
+            database.put(hash, 1, hash).unwrap();
+        }
+
+        database.save().unwrap();
+
+        for hash in 0..32 {
+            database.put(hash, 2, hash * 2).unwrap();
+        }
+
+        drop(database);
+
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::open(config).unwrap();
+
+        assert_eq!(64, database.count().unwrap());
+
+        for hash in 0..32 {
+            assert_eq!(Some(hash), database.get(&hash, &1).unwrap());
+            assert_eq!(Some(hash * 2), database.get(&hash, &2).unwrap());
+        }
+    }
+
+    #[test]
+    fn save_must_encode_snapshot_with_codec() {
+        let path = env::temp_dir().join(format!("htdb-shared-codec-{}", process::id()));
+        let config = Config::default().set_storage_path(&path);
+        let database: SharedDatabase<u32, u32, u32, RandomState, Cbor> =
+            SharedDatabase::with_codec(config.clone(), RandomState::new(), Cbor);
+
+        database.put(1, 1, 10).unwrap();
+        database.save().unwrap();
+
+        // Encoding is recorded at the end of snapshot header.
+        let bytes = fs::read(path.join("full.htdb")).unwrap();
+
+        assert_eq!(Cbor.id().to_le_bytes(), bytes[36..40]);
+
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(config);
+
+        database.load().unwrap();
+
+        assert_eq!(Some(10), database.get(&1, &1).unwrap());
+    }
+
+    #[test]
+    fn update_must_log_changed_values() {
+        let path = env::temp_dir().join(format!("htdb-shared-update-{}", process::id()));
+
+        fs::remove_dir_all(&path).ok();
+
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::open(config).unwrap();
+
+        database.put(1, 1, 10).unwrap();
+
+        let previous = database.update(1, 1, |value| value.replace(11)).unwrap();
+
+        database.update(2, 1, |value| *value = Some(20)).unwrap();
+        database.update(1, 2, |value| *value = None).unwrap();
+        drop(database);
+
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::open(config).unwrap();
+
+        assert_eq!(Some(10), previous);
+        assert_eq!(Some(11), database.get(&1, &1).unwrap());
+        assert_eq!(Some(20), database.get(&2, &1).unwrap());
+        assert_eq!(2, database.count().unwrap());
+    }
+
+    #[test]
+    fn merge_must_keep_expiry_time() {
+        let database: SharedDatabase<u32, u32, Vec<u8>> = SharedDatabase::new(Config::default());
+
+        database
+            .put_with_ttl(1, 1, 1i64.to_le_bytes().to_vec(), Duration::from_secs(3600))
+            .unwrap();
+
+        let expires_at = database.read_shard(&1).unwrap().partition_map()[&1].expires_at(&1);
+
+        database
+            .merge_with(1, 1, 2i64.to_le_bytes().to_vec(), &IntegerAdd)
+            .unwrap();
+
+        assert_eq!(true, expires_at.is_some());
+        assert_eq!(
+            expires_at,
+            database.read_shard(&1).unwrap().partition_map()[&1].expires_at(&1)
+        );
+        assert_eq!(
+            Some(3i64.to_le_bytes().to_vec()),
+            database.get(&1, &1).unwrap()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::mem;
+
+/// Approximate number of bytes taken by value in memory, including its heap allocation. Used
+/// to split pages and swap them out by `Config::max_page_bytes` and `Config::memory_budget`.
+/// Estimate is taken on every change of page, so it should be cheap: implemented for byte
+/// vectors, strings and primitives, other types implement it themselves or are wrapped into
+/// `SerializedSize`.
+pub trait EstimateSize {
+    fn estimate_size(&self) -> usize;
+}
+
+macro_rules! impl_fixed_size {
+    ($($type:ty),*) => {
+        $(
+            impl EstimateSize for $type {
+                fn estimate_size(&self) -> usize {
+                    mem::size_of::<$type>()
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_size!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+impl EstimateSize for Vec<u8> {
+    fn estimate_size(&self) -> usize {
+        mem::size_of::<Vec<u8>>() + self.len()
+    }
+}
+
+impl EstimateSize for String {
+    fn estimate_size(&self) -> usize {
+        mem::size_of::<String>() + self.len()
+    }
+}
+
+impl EstimateSize for str {
+    fn estimate_size(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Wrapper which estimates size of any serializable value as size of the value plus length of
+/// its bincode encoding. Encoding is computed on every estimate, so it is slower than own
+/// implementation of `EstimateSize`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct SerializedSize<T>(pub T);
+
+impl<T> EstimateSize for SerializedSize<T>
+where
+    T: Serialize,
+{
+    fn estimate_size(&self) -> usize {
+        let encoded = bincode::serialized_size(&self.0).unwrap_or(0) as usize;
+
+        mem::size_of::<T>() + encoded
+    }
+}
+
+/// Returns approximate size of entry stored in page.
+pub(crate) fn entry_size<K, V>(key: &K, value: &V) -> usize
+where
+    K: EstimateSize,
+    V: EstimateSize,
+{
+    key.estimate_size() + value.estimate_size()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::EstimateSize;
+    use crate::SerializedSize;
+
+    #[test]
+    fn serialized_size_must_include_encoded_length() {
+        let value = SerializedSize((1u32, vec![0u8; 10]));
+
+        // Tuple takes 32 bytes in memory, encoded as 4 bytes, length prefix and 10 bytes.
+        assert_eq!(32 + 4 + 8 + 10, value.estimate_size());
+        assert_eq!(4, 1u32.estimate_size());
+        assert_eq!(24 + 3, "abc".to_string().estimate_size());
+    }
+}
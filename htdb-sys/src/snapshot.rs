@@ -0,0 +1,586 @@
+use crate::BuiltinCodec;
+use crate::Codec;
+use crate::Compression;
+use crate::DatabaseError;
+use crc32fast::Hasher as Crc32Hasher;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression as DeflateLevel;
+use lz4_flex::frame::FrameDecoder as Lz4Decoder;
+use lz4_flex::frame::FrameEncoder as Lz4Encoder;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+const MAGIC: [u8; 4] = *b"HTDB";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 40;
+
+/// Fixed size header written before compressed snapshot payload. All numbers are little endian.
+#[derive(Debug)]
+pub struct SnapshotHeader {
+    version: u32,
+    type_tag: u32,
+    entries: u64,
+    payload_length: u64,
+    checksum: u32,
+    compression: u32,
+    encoding: u32,
+}
+
+impl SnapshotHeader {
+    fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.type_tag.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.entries.to_le_bytes());
+        bytes[20..28].copy_from_slice(&self.payload_length.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[32..36].copy_from_slice(&self.compression.to_le_bytes());
+        bytes[36..40].copy_from_slice(&self.encoding.to_le_bytes());
+        bytes
+    }
+
+    fn read<R>(reader: &mut R) -> Result<SnapshotHeader, DatabaseError>
+    where
+        R: Read,
+    {
+        let mut bytes = [0; HEADER_SIZE];
+
+        reader
+            .read_exact(&mut bytes)
+            .map_err(|_| DatabaseError::corrupted_snapshot("Snapshot is shorter than header"))?;
+
+        if bytes[0..4] != MAGIC {
+            return Err(DatabaseError::corrupted_snapshot(
+                "Snapshot does not start with HTDB magic bytes",
+            ));
+        }
+
+        let version = u32_at(&bytes, 4);
+
+        if version != FORMAT_VERSION {
+            return Err(DatabaseError::version_mismatch(FORMAT_VERSION, version));
+        }
+
+        Ok(SnapshotHeader {
+            version,
+            type_tag: u32_at(&bytes, 8),
+            entries: u64_at(&bytes, 12),
+            payload_length: u64_at(&bytes, 20),
+            checksum: u32_at(&bytes, 28),
+            compression: u32_at(&bytes, 32),
+            encoding: u32_at(&bytes, 36),
+        })
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+
+    value.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+/// Returns checksum of partition, key and value type names, used as type tag unless
+/// `Config::set_type_tag` overrides it.
+pub fn type_tag<H, K, V>() -> u32 {
+    let mut hasher = Crc32Hasher::new();
+
+    for name in [
+        any::type_name::<H>(),
+        any::type_name::<K>(),
+        any::type_name::<V>(),
+    ] {
+        hasher.update(name.as_bytes());
+        hasher.update(&[0]);
+    }
+
+    hasher.finalize()
+}
+
+/// Returns `true` if file at `path` starts with snapshot header. Snapshots saved before header
+/// was introduced contain only compressed partitions.
+pub fn has_header(path: &Path) -> Result<bool, DatabaseError> {
+    let mut file = File::open(path).map_err(DatabaseError::open_file_error)?;
+    let mut bytes = [0; MAGIC.len()];
+
+    Ok(file.read_exact(&mut bytes).is_ok() && bytes == MAGIC)
+}
+
+/// Reads snapshot saved before header was introduced, it is always encoded by bincode and
+/// compressed with deflate. File is accepted only if it decodes completely, anything else is
+/// reported as corrupted snapshot.
+pub fn read_legacy_snapshot<T>(path: &Path) -> Result<T, DatabaseError>
+where
+    T: DeserializeOwned,
+{
+    let file = File::open(path).map_err(DatabaseError::open_file_error)?;
+    let mut decoder = DeflateDecoder::new(BufReader::new(file));
+    let corrupted = || {
+        DatabaseError::corrupted_snapshot(
+            "Snapshot has neither HTDB header nor layout of snapshot saved without header",
+        )
+    };
+    let data = bincode::deserialize_from(&mut decoder).map_err(|_| corrupted())?;
+
+    match decoder.read(&mut [0]) {
+        Ok(0) => Ok(data),
+        _ => Err(corrupted()),
+    }
+}
+
+/// Writes header and `data` encoded by `codec` and compressed with `compression` to `path`,
+/// file is synchronized to disk.
+pub fn write_snapshot<T, C>(
+    path: &Path,
+    type_tag: u32,
+    entries: usize,
+    compression: Compression,
+    codec: &C,
+    data: &T,
+) -> Result<(), DatabaseError>
+where
+    T: Serialize,
+    C: Codec,
+{
+    let file = File::create(path).map_err(DatabaseError::create_file_error)?;
+    let mut writer = BufWriter::new(file);
+
+    writer
+        .write_all(&[0; HEADER_SIZE])
+        .map_err(DatabaseError::encoding_error)?;
+
+    let checksum_writer = ChecksumWriter::new(writer);
+    let checksum_writer = match compression {
+        Compression::None => {
+            let mut encoder = checksum_writer;
+
+            codec.encode(&mut encoder, data)?;
+            encoder
+        }
+        Compression::Deflate(level) => {
+            let mut encoder = DeflateEncoder::new(checksum_writer, DeflateLevel::new(level));
+
+            codec.encode(&mut encoder, data)?;
+            encoder.finish().map_err(DatabaseError::encoding_error)?
+        }
+        Compression::Zstd(level) => {
+            let mut encoder =
+                ZstdEncoder::new(checksum_writer, level).map_err(DatabaseError::encoding_error)?;
+
+            codec.encode(&mut encoder, data)?;
+            encoder.finish().map_err(DatabaseError::encoding_error)?
+        }
+        Compression::Lz4 => {
+            let mut encoder = Lz4Encoder::new(checksum_writer);
+
+            codec.encode(&mut encoder, data)?;
+            encoder
+                .finish()
+                .map_err(|error| DatabaseError::encoding_error(error.into()))?
+        }
+    };
+    let header = SnapshotHeader {
+        version: FORMAT_VERSION,
+        type_tag,
+        entries: entries as u64,
+        payload_length: checksum_writer.length,
+        checksum: checksum_writer.hasher.finalize(),
+        compression: compression.compression_id(),
+        encoding: codec.id(),
+    };
+    let mut writer = checksum_writer.inner;
+
+    writer
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| writer.write_all(&header.to_bytes()))
+        .map_err(DatabaseError::encoding_error)?;
+
+    let file = writer
+        .into_inner()
+        .map_err(|error| DatabaseError::encoding_error(error.into_error()))?;
+
+    file.sync_all().map_err(DatabaseError::encoding_error)
+}
+
+/// Reads snapshot from `path` validating its header and payload checksum. Returns data and
+/// number of entries recorded in the header. Payload is decoded by `codec` if header records its identifier, otherwise by built-in codec recorded in
+/// the header.
+pub fn read_snapshot<T, C>(
+    path: &Path,
+    type_tag: u32,
+    codec: &C,
+) -> Result<(T, usize), DatabaseError>
+where
+    T: DeserializeOwned,
+    C: Codec,
+{
+    let file = File::open(path).map_err(DatabaseError::open_file_error)?;
+    let mut reader = BufReader::new(file);
+    let header = SnapshotHeader::read(&mut reader)?;
+
+    if header.type_tag != type_tag {
+        return Err(DatabaseError::type_mismatch(
+            "Snapshot was saved with different type tag",
+        ));
+    }
+
+    let data = if header.encoding == codec.id() {
+        read_payload(&header, reader, codec)?
+    } else if let Some(builtin) = BuiltinCodec::from_id(header.encoding) {
+        read_payload(&header, reader, &builtin)?
+    } else {
+        return Err(DatabaseError::codec_mismatch(header.encoding, codec.id()));
+    };
+
+    Ok((data, header.entries as usize))
+}
+
+/// Decompresses payload following `header`, decodes it by `codec` and verifies its checksum.
+fn read_payload<R, T, C>(header: &SnapshotHeader, reader: R, codec: &C) -> Result<T, DatabaseError>
+where
+    R: Read,
+    T: DeserializeOwned,
+    C: Codec,
+{
+    let checksum_reader = ChecksumReader::new(reader);
+    let (result, mut checksum_reader) = match header.compression {
+        0 => {
+            let mut decoder = checksum_reader;
+
+            (codec.decode(&mut decoder), decoder)
+        }
+        1 => {
+            let mut decoder = DeflateDecoder::new(checksum_reader);
+
+            (codec.decode(&mut decoder), decoder.into_inner())
+        }
+        2 => {
+            let mut decoder =
+                ZstdDecoder::new(checksum_reader).map_err(DatabaseError::open_file_error)?;
+
+            (codec.decode(&mut decoder), decoder.finish().into_inner())
+        }
+        3 => {
+            let mut decoder = Lz4Decoder::new(checksum_reader);
+
+            (codec.decode(&mut decoder), decoder.into_inner())
+        }
+        compression => {
+            return Err(DatabaseError::corrupted_snapshot(format!(
+                "Snapshot is compressed with unknown compression {}",
+                compression
+            )))
+        }
+    };
+
+    io::copy(&mut checksum_reader, &mut io::sink()).map_err(DatabaseError::open_file_error)?;
+
+    if checksum_reader.length != header.payload_length
+        || checksum_reader.hasher.finalize() != header.checksum
+    {
+        return Err(DatabaseError::corrupted_snapshot(
+            "Snapshot checksum does not match its content",
+        ));
+    }
+
+    result
+}
+
+struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Crc32Hasher,
+    length: u64,
+}
+
+impl<W> ChecksumWriter<W> {
+    fn new(inner: W) -> ChecksumWriter<W> {
+        ChecksumWriter {
+            inner,
+            hasher: Crc32Hasher::new(),
+            length: 0,
+        }
+    }
+}
+
+impl<W> Write for ChecksumWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.inner.write(buf)?;
+
+        self.hasher.update(&buf[..size]);
+        self.length += size as u64;
+
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ChecksumReader<R> {
+    inner: R,
+    hasher: Crc32Hasher,
+    length: u64,
+}
+
+impl<R> ChecksumReader<R> {
+    fn new(inner: R) -> ChecksumReader<R> {
+        ChecksumReader {
+            inner,
+            hasher: Crc32Hasher::new(),
+            length: 0,
+        }
+    }
+}
+
+impl<R> Read for ChecksumReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+
+        self.hasher.update(&buf[..size]);
+        self.length += size as u64;
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::read_snapshot;
+    use crate::snapshot::write_snapshot;
+    use crate::Bincode;
+    use crate::Cbor;
+    use crate::Codec;
+    use crate::Compression;
+    use crate::DatabaseError;
+    use crate::MessagePack;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// Bincode with identifier of codec which is not built-in.
+    #[derive(Debug)]
+    struct CustomCodec;
+
+    impl Codec for CustomCodec {
+        fn id(&self) -> u32 {
+            256
+        }
+
+        fn encode<W, T>(&self, writer: W, value: &T) -> Result<(), DatabaseError>
+        where
+            W: Write,
+            T: Serialize + ?Sized,
+        {
+            Bincode.encode(writer, value)
+        }
+
+        fn decode<R, T>(&self, reader: R) -> Result<T, DatabaseError>
+        where
+            R: Read,
+            T: DeserializeOwned,
+        {
+            Bincode.decode(reader)
+        }
+    }
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("htdb-snapshot-{}-{}", name, process::id()));
+
+        fs::create_dir_all(&path).unwrap();
+
+        path.join("full.htdb")
+    }
+
+    #[test]
+    fn read_must_return_written_data() {
+        let path = snapshot_path("read");
+        let type_tag = 1;
+
+        write_snapshot(
+            &path,
+            type_tag,
+            3,
+            Compression::Deflate(6),
+            &Bincode,
+            &vec![1u32, 2, 3],
+        )
+        .unwrap();
+
+        let (data, entries): (Vec<u32>, _) = read_snapshot(&path, type_tag, &Bincode).unwrap();
+
+        assert_eq!(vec![1, 2, 3], data);
+        assert_eq!(3, entries);
+    }
+
+    #[test]
+    fn read_must_decode_all_codecs() {
+        let path = snapshot_path("codecs");
+        let type_tag = 1;
+        let data: Vec<u32> = (0..1000).collect();
+
+        for compression in [
+            Compression::None,
+            Compression::Deflate(1),
+            Compression::Zstd(3),
+            Compression::Lz4,
+        ] {
+            write_snapshot(&path, type_tag, data.len(), compression, &Bincode, &data).unwrap();
+
+            let (result, entries): (Vec<u32>, _) =
+                read_snapshot(&path, type_tag, &Bincode).unwrap();
+
+            assert_eq!(data, result);
+            assert_eq!(1000, entries);
+        }
+    }
+
+    #[test]
+    fn read_must_reject_foreign_file() {
+        let path = snapshot_path("foreign");
+
+        fs::write(&path, vec![0; 64]).unwrap();
+
+        let result = read_snapshot::<Vec<u32>, _>(&path, 0, &Bincode);
+
+        assert!(matches!(
+            result,
+            Err(DatabaseError::CorruptedSnapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn read_must_reject_damaged_payload() {
+        let path = snapshot_path("damaged");
+        let type_tag = 1;
+
+        write_snapshot(
+            &path,
+            type_tag,
+            100,
+            Compression::Deflate(6),
+            &Bincode,
+            &(0..100u32).collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+
+        bytes[last] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+
+        let result = read_snapshot::<Vec<u32>, _>(&path, type_tag, &Bincode);
+
+        assert!(matches!(
+            result,
+            Err(DatabaseError::CorruptedSnapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn read_must_reject_other_version() {
+        let path = snapshot_path("version");
+        let type_tag = 1;
+
+        write_snapshot(&path, type_tag, 0, Compression::Deflate(6), &Bincode, &0u32).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+
+        bytes[4] = 99;
+        fs::write(&path, bytes).unwrap();
+
+        let result = read_snapshot::<u32, _>(&path, type_tag, &Bincode);
+
+        assert!(matches!(result, Err(DatabaseError::VersionMismatch { .. })));
+    }
+
+    #[test]
+    fn read_must_reject_other_types() {
+        let path = snapshot_path("types");
+
+        write_snapshot(&path, 1, 0, Compression::Deflate(6), &Bincode, &0u32).unwrap();
+
+        let result = read_snapshot::<u32, _>(&path, 2, &Bincode);
+
+        assert!(matches!(result, Err(DatabaseError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn read_must_decode_all_encodings() {
+        let path = snapshot_path("encodings");
+        let type_tag = 1;
+        let data: Vec<u32> = (0..1000).collect();
+
+        write_snapshot(&path, type_tag, 1000, Compression::Lz4, &MessagePack, &data).unwrap();
+
+        let (result, _): (Vec<u32>, _) = read_snapshot(&path, type_tag, &MessagePack).unwrap();
+
+        assert_eq!(data, result);
+
+        write_snapshot(&path, type_tag, 1000, Compression::None, &Cbor, &data).unwrap();
+
+        let (result, _): (Vec<u32>, _) = read_snapshot(&path, type_tag, &Cbor).unwrap();
+
+        assert_eq!(data, result);
+    }
+
+    #[test]
+    fn read_must_decode_builtin_encoding_of_header() {
+        let path = snapshot_path("builtin-encoding");
+
+        write_snapshot(&path, 1, 0, Compression::None, &Cbor, &7u32).unwrap();
+
+        let (data, _): (u32, _) = read_snapshot(&path, 1, &Bincode).unwrap();
+
+        assert_eq!(7, data);
+    }
+
+    #[test]
+    fn read_must_reject_other_encoding() {
+        let path = snapshot_path("encoding");
+
+        write_snapshot(&path, 1, 0, Compression::None, &CustomCodec, &0u32).unwrap();
+
+        let result = read_snapshot::<u32, _>(&path, 1, &Bincode);
+
+        assert!(matches!(result, Err(DatabaseError::CodecMismatch { .. })));
+
+        let (data, _): (u32, _) = read_snapshot(&path, 1, &CustomCodec).unwrap();
+
+        assert_eq!(0, data);
+    }
+}
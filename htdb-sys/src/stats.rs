@@ -0,0 +1,148 @@
+use crate::pages::Counters;
+use crate::pages::Pages;
+use crate::EstimateSize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::time::Duration;
+
+/// Layout and activity of database at the moment `stats` was called. Page fill is number of
+/// entries in page divided by `Config::max_page_size`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    partitions: usize,
+    pages: usize,
+    entries: usize,
+    min_page_size: usize,
+    max_page_size: usize,
+    page_capacity: usize,
+    splits: u64,
+    removed_pages: u64,
+    memory: usize,
+    last_save: Option<Duration>,
+    last_load: Option<Duration>,
+}
+
+impl Stats {
+    pub(crate) fn new(page_capacity: usize, counters: &Counters) -> Stats {
+        Stats {
+            page_capacity,
+            splits: counters.splits(),
+            removed_pages: counters.removed_pages(),
+            ..Stats::default()
+        }
+    }
+
+    /// Adds pages of single partition.
+    pub(crate) fn add_partition<K, V>(&mut self, pages: &Pages<K, V>)
+    where
+        K: Ord + Clone + Serialize + DeserializeOwned + Debug + EstimateSize,
+        V: Serialize + DeserializeOwned + Debug + EstimateSize,
+    {
+        self.partitions += 1;
+        self.entries += pages.size();
+        self.memory += pages.memory();
+
+        for size in pages.page_sizes() {
+            if self.pages == 0 {
+                self.min_page_size = size;
+                self.max_page_size = size;
+            } else {
+                self.min_page_size = self.min_page_size.min(size);
+                self.max_page_size = self.max_page_size.max(size);
+            }
+
+            self.pages += 1;
+        }
+    }
+
+    /// Adds statistics of another part of the same database, parts share configuration.
+    pub(crate) fn merge(&mut self, other: &Stats) {
+        self.page_capacity = other.page_capacity;
+
+        if other.pages > 0 {
+            if self.pages == 0 {
+                self.min_page_size = other.min_page_size;
+                self.max_page_size = other.max_page_size;
+            } else {
+                self.min_page_size = self.min_page_size.min(other.min_page_size);
+                self.max_page_size = self.max_page_size.max(other.max_page_size);
+            }
+        }
+
+        self.partitions += other.partitions;
+        self.pages += other.pages;
+        self.entries += other.entries;
+        self.splits += other.splits;
+        self.removed_pages += other.removed_pages;
+        self.memory += other.memory;
+    }
+
+    pub(crate) fn set_durations(
+        &mut self,
+        last_save: Option<Duration>,
+        last_load: Option<Duration>,
+    ) {
+        self.last_save = last_save;
+        self.last_load = last_load;
+    }
+
+    pub fn partitions(&self) -> usize {
+        self.partitions
+    }
+
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    /// Returns fill of the emptiest page, zero if database is empty.
+    pub fn min_page_fill(&self) -> f64 {
+        self.fill(self.min_page_size as f64)
+    }
+
+    pub fn average_page_fill(&self) -> f64 {
+        match self.pages {
+            0 => 0.0,
+            pages => self.fill(self.entries as f64 / pages as f64),
+        }
+    }
+
+    pub fn max_page_fill(&self) -> f64 {
+        self.fill(self.max_page_size as f64)
+    }
+
+    /// Returns number of page splits since database was created.
+    pub fn splits(&self) -> u64 {
+        self.splits
+    }
+
+    /// Returns number of pages removed since database was created, either emptied or merged
+    /// into adjacent page.
+    pub fn removed_pages(&self) -> u64 {
+        self.removed_pages
+    }
+
+    /// Returns approximate size of resident pages in bytes as estimated by `EstimateSize`.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    pub fn last_save(&self) -> Option<Duration> {
+        self.last_save
+    }
+
+    pub fn last_load(&self) -> Option<Duration> {
+        self.last_load
+    }
+
+    fn fill(&self, size: f64) -> f64 {
+        match self.page_capacity {
+            0 => 0.0,
+            capacity => size / capacity as f64,
+        }
+    }
+}
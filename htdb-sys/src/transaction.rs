@@ -0,0 +1,361 @@
+use crate::Bincode;
+use crate::Codec;
+use crate::Database;
+use crate::DatabaseError;
+use crate::EstimateSize;
+use crate::SharedDatabase;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+
+/// Changes made in transaction, `None` marks deleted value.
+pub(crate) type Writes<H, K, V> = HashMap<H, BTreeMap<K, Option<V>>>;
+
+/// Values replaced by applied transaction changes in order of application with their expiry
+/// times, `None` marks value that did not exist.
+pub(crate) type Undo<H, K, V> = Vec<(H, K, Option<V>, Option<u64>)>;
+
+/// Group of changes applied to database at once. Changes are kept in memory until `commit` is
+/// called, reads inside transaction see its own changes. Dropped transaction is rolled back.
+#[derive(Debug)]
+pub struct Transaction<'a, H, K, V, S = RandomState, C = Bincode>
+where
+    K: Ord,
+{
+    database: &'a mut Database<H, K, V, S, C>,
+    writes: Writes<H, K, V>,
+}
+
+impl<'a, H, K, V, S, C> Transaction<'a, H, K, V, S, C>
+where
+    H: Eq + Hash + Clone + Serialize + DeserializeOwned + Debug,
+    K: Ord + Clone + Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    V: Default + Serialize + DeserializeOwned + Debug + EstimateSize,
+    S: BuildHasher,
+    C: Codec,
+{
+    pub(crate) fn new(database: &'a mut Database<H, K, V, S, C>) -> Transaction<'a, H, K, V, S, C> {
+        Transaction {
+            database,
+            writes: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, hash_key: &H, tree_key: &K) -> Result<Option<&V>, DatabaseError> {
+        // Written values are looked up by field, so database stays available for mutable borrow.
+        if let Some(data) = self
+            .writes
+            .get(hash_key)
+            .and_then(|tree| tree.get(tree_key))
+        {
+            return Ok(data.as_ref());
+        }
+
+        self.database.get(hash_key, tree_key)
+    }
+
+    pub fn contains(&mut self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        if let Some(data) = self.write(hash_key, tree_key) {
+            return Ok(data.is_some());
+        }
+
+        self.database.contains(hash_key, tree_key)
+    }
+
+    /// Returns `true` if value did not exist before.
+    pub fn put(&mut self, hash_key: H, tree_key: K, data: V) -> Result<bool, DatabaseError> {
+        let result = !self.contains(&hash_key, &tree_key)?;
+
+        self.writes
+            .entry(hash_key)
+            .or_default()
+            .insert(tree_key, Some(data));
+
+        Ok(result)
+    }
+
+    /// Returns `true` if value existed before.
+    pub fn delete(&mut self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        let result = self.contains(hash_key, tree_key)?;
+
+        self.writes
+            .entry(hash_key.clone())
+            .or_default()
+            .insert(tree_key.clone(), None);
+
+        Ok(result)
+    }
+
+    /// Makes all changes visible. If commit fails database is left unchanged, unless applied
+    /// changes could not be reverted, which is reported as `DatabaseError::RevertError`.
+    pub fn commit(self) -> Result<(), DatabaseError> {
+        self.database.commit(self.writes)
+    }
+
+    /// Discards all changes, same as dropping transaction.
+    pub fn rollback(self) {}
+
+    fn write(&self, hash_key: &H, tree_key: &K) -> Option<&Option<V>> {
+        self.writes.get(hash_key)?.get(tree_key)
+    }
+}
+
+/// Transaction of `SharedDatabase`. Changes are kept in memory until `commit` is called, reads
+/// inside transaction see its own changes and changes committed by other threads. Dropped
+/// transaction is rolled back.
+#[derive(Debug)]
+pub struct SharedTransaction<H, K, V, S = RandomState, C = Bincode>
+where
+    K: Ord,
+{
+    database: SharedDatabase<H, K, V, S, C>,
+    writes: Writes<H, K, V>,
+}
+
+impl<H, K, V, S, C> SharedTransaction<H, K, V, S, C>
+where
+    H: Eq + Hash + Clone + Serialize + DeserializeOwned + Debug + Send + Sync + 'static,
+    K: Ord
+        + Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    V: Clone
+        + Default
+        + Serialize
+        + DeserializeOwned
+        + Debug
+        + EstimateSize
+        + Send
+        + Sync
+        + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+    C: Codec + Send + Sync + 'static,
+{
+    pub(crate) fn new(database: SharedDatabase<H, K, V, S, C>) -> SharedTransaction<H, K, V, S, C> {
+        SharedTransaction {
+            database,
+            writes: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, hash_key: &H, tree_key: &K) -> Result<Option<V>, DatabaseError> {
+        if let Some(data) = self.write(hash_key, tree_key) {
+            return Ok(data.clone());
+        }
+
+        self.database.get(hash_key, tree_key)
+    }
+
+    pub fn contains(&self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        if let Some(data) = self.write(hash_key, tree_key) {
+            return Ok(data.is_some());
+        }
+
+        self.database.contains(hash_key, tree_key)
+    }
+
+    /// Returns `true` if value did not exist before.
+    pub fn put(&mut self, hash_key: H, tree_key: K, data: V) -> Result<bool, DatabaseError> {
+        let result = !self.contains(&hash_key, &tree_key)?;
+
+        self.writes
+            .entry(hash_key)
+            .or_default()
+            .insert(tree_key, Some(data));
+
+        Ok(result)
+    }
+
+    /// Returns `true` if value existed before.
+    pub fn delete(&mut self, hash_key: &H, tree_key: &K) -> Result<bool, DatabaseError> {
+        let result = self.contains(hash_key, tree_key)?;
+
+        self.writes
+            .entry(hash_key.clone())
+            .or_default()
+            .insert(tree_key.clone(), None);
+
+        Ok(result)
+    }
+
+    /// Makes all changes visible at once. If commit fails database is left unchanged, unless
+    /// applied changes could not be reverted, which is reported as `DatabaseError::RevertError`.
+    pub fn commit(self) -> Result<(), DatabaseError> {
+        self.database.commit(self.writes)
+    }
+
+    /// Discards all changes, same as dropping transaction.
+    pub fn rollback(self) {}
+
+    fn write(&self, hash_key: &H, tree_key: &K) -> Option<&Option<V>> {
+        self.writes.get(hash_key)?.get(tree_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+    use crate::Database;
+    use crate::SharedDatabase;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn get_must_see_own_writes() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+        database.put(1, 2, 20).unwrap();
+
+        let mut transaction = database.begin();
+
+        assert_eq!(true, transaction.put(1, 3, 30).unwrap());
+        assert_eq!(false, transaction.put(1, 1, 11).unwrap());
+        assert_eq!(true, transaction.delete(&1, &2).unwrap());
+        assert_eq!(Some(&11), transaction.get(&1, &1).unwrap());
+        assert_eq!(None, transaction.get(&1, &2).unwrap());
+        assert_eq!(Some(&30), transaction.get(&1, &3).unwrap());
+    }
+
+    #[test]
+    fn commit_must_apply_all_changes() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+
+        let mut transaction = database.begin();
+
+        transaction.put(1, 2, 20).unwrap();
+        transaction.put(2, 1, 30).unwrap();
+        transaction.delete(&1, &1).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(None, database.get(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&1, &2).unwrap());
+        assert_eq!(Some(&30), database.get(&2, &1).unwrap());
+        assert_eq!(2, database.count().unwrap());
+    }
+
+    #[test]
+    fn rollback_must_discard_changes() {
+        let mut database: Database<u32, u32, u32> = Database::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+
+        let mut transaction = database.begin();
+
+        transaction.put(1, 2, 20).unwrap();
+        transaction.delete(&1, &1).unwrap();
+        transaction.rollback();
+
+        {
+            let mut transaction = database.begin();
+
+            transaction.put(2, 1, 30).unwrap();
+        }
+
+        assert_eq!(Some(&10), database.get(&1, &1).unwrap());
+        assert_eq!(None, database.get(&1, &2).unwrap());
+        assert_eq!(None, database.get(&2, &1).unwrap());
+        assert_eq!(1, database.count().unwrap());
+    }
+
+    #[test]
+    fn open_must_replay_committed_transactions() {
+        let path = env::temp_dir().join(format!("htdb-transaction-{}", process::id()));
+
+        fs::remove_dir_all(&path).ok();
+
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let mut database: Database<u32, u32, u32> = Database::open(config.clone()).unwrap();
+        let mut transaction = database.begin();
+
+        transaction.put(1, 1, 10).unwrap();
+        transaction.put(2, 2, 20).unwrap();
+        transaction.commit().unwrap();
+
+        let mut transaction = database.begin();
+
+        transaction.put(3, 3, 30).unwrap();
+        drop(transaction);
+        drop(database);
+
+        let mut database: Database<u32, u32, u32> = Database::open(config).unwrap();
+
+        assert_eq!(Some(&10), database.get(&1, &1).unwrap());
+        assert_eq!(Some(&20), database.get(&2, &2).unwrap());
+        assert_eq!(None, database.get(&3, &3).unwrap());
+    }
+
+    #[test]
+    fn shared_commit_must_apply_changes_of_all_shards() {
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::new(Config::default());
+
+        database.put(1, 1, 10).unwrap();
+
+        let mut transaction = database.begin();
+
+        for hash in 0..32 {
+            assert_eq!(true, transaction.put(hash, 2, hash).unwrap());
+        }
+
+        assert_eq!(true, transaction.delete(&1, &1).unwrap());
+        assert_eq!(None, transaction.get(&1, &1).unwrap());
+        assert_eq!(Some(10), database.get(&1, &1).unwrap());
+        assert_eq!(None, database.get(&5, &2).unwrap());
+
+        transaction.commit().unwrap();
+
+        assert_eq!(None, database.get(&1, &1).unwrap());
+        assert_eq!(32, database.count().unwrap());
+
+        for hash in 0..32 {
+            assert_eq!(Some(hash), database.get(&hash, &2).unwrap());
+        }
+    }
+
+    #[test]
+    fn shared_open_must_replay_committed_transactions() {
+        let path = env::temp_dir().join(format!("htdb-shared-transaction-{}", process::id()));
+
+        fs::remove_dir_all(&path).ok();
+
+        let config = Config::default()
+            .set_storage_path(&path)
+            .set_write_ahead_log(true);
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::open(config.clone()).unwrap();
+        let mut transaction = database.begin();
+
+        for hash in 0..32 {
+            transaction.put(hash, hash, hash * 10).unwrap();
+        }
+
+        transaction.commit().unwrap();
+
+        let mut transaction = database.begin();
+
+        transaction.put(40, 40, 400).unwrap();
+        transaction.rollback();
+        drop(database);
+
+        let database: SharedDatabase<u32, u32, u32> = SharedDatabase::open(config).unwrap();
+
+        assert_eq!(32, database.count().unwrap());
+        assert_eq!(Some(310), database.get(&31, &31).unwrap());
+        assert_eq!(None, database.get(&40, &40).unwrap());
+    }
+}
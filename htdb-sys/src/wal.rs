@@ -0,0 +1,431 @@
+use crate::DatabaseError;
+use crc32fast::Hasher as Crc32Hasher;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::ops::Bound;
+use std::path::Path;
+
+/// Size of record header, length of encoded record followed by checksum of length and record.
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// Single database mutation. Records are written with references and read back as owned values.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum LogRecord<H, K, V> {
+    Put {
+        hash_key: H,
+        tree_key: K,
+        data: V,
+    },
+    Delete {
+        hash_key: H,
+        tree_key: K,
+    },
+    /// Mutations of committed transaction, written as one record so replay never applies part
+    /// of them.
+    Batch(Vec<LogRecord<H, K, V>>),
+    DropPartition {
+        hash_key: H,
+    },
+    RenamePartition {
+        hash_key: H,
+        new_hash_key: H,
+    },
+    /// Put of value with time-to-live, `expires_at` is absolute time in milliseconds since UNIX
+    /// epoch.
+    PutWithExpiry {
+        hash_key: H,
+        tree_key: K,
+        data: V,
+        expires_at: u64,
+    },
+    /// Removal of all entries of partition within bounds.
+    DeleteRange {
+        hash_key: H,
+        range_start: Bound<K>,
+        range_end: Bound<K>,
+    },
+}
+
+impl<H, K, V> LogRecord<H, K, V> {
+    /// Returns hash key of partition changed by record, or `None` if record changes several
+    /// partitions.
+    pub fn hash_key(&self) -> Option<&H> {
+        match self {
+            LogRecord::Put { hash_key, .. } => Some(hash_key),
+            LogRecord::Delete { hash_key, .. } => Some(hash_key),
+            LogRecord::DropPartition { hash_key } => Some(hash_key),
+            LogRecord::PutWithExpiry { hash_key, .. } => Some(hash_key),
+            LogRecord::DeleteRange { hash_key, .. } => Some(hash_key),
+            LogRecord::Batch(_) | LogRecord::RenamePartition { .. } => None,
+        }
+    }
+}
+
+/// Append-only log of mutations applied after the last saved snapshot.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    writer: BufWriter<File>,
+    sync: bool,
+}
+
+impl WriteAheadLog {
+    /// Opens log at `path` passing every complete record to `apply`. Log is cut at the first
+    /// incomplete or damaged record (left by interrupted write), it and all following bytes are
+    /// discarded.
+    pub fn open<H, K, V, F>(path: &Path, mut apply: F) -> Result<WriteAheadLog, DatabaseError>
+    where
+        H: DeserializeOwned,
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        F: FnMut(LogRecord<H, K, V>) -> Result<(), DatabaseError>,
+    {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(DatabaseError::create_file_error)?;
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(DatabaseError::open_file_error)?;
+        let file_length = file
+            .metadata()
+            .map_err(DatabaseError::open_file_error)?
+            .len();
+        let mut reader = BufReader::new(file);
+        let mut valid_length = 0;
+
+        while let Some(payload) = read_payload(&mut reader, file_length - valid_length)? {
+            let record = bincode::deserialize(&payload).map_err(DatabaseError::serialize_error)?;
+
+            apply(record)?;
+            valid_length += (RECORD_HEADER_SIZE + payload.len()) as u64;
+        }
+
+        let file = reader.into_inner();
+
+        file.set_len(valid_length)
+            .map_err(DatabaseError::encoding_error)?;
+
+        Ok(WriteAheadLog {
+            writer: BufWriter::new(file),
+            sync: true,
+        })
+    }
+
+    /// Opens log of database which was not restored from it. Fails with `UnreplayedLog` if log
+    /// contains records, otherwise they would be removed by the next save.
+    pub fn create<H, K, V>(path: &Path) -> Result<WriteAheadLog, DatabaseError>
+    where
+        H: DeserializeOwned,
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        WriteAheadLog::open::<H, K, V, _>(path, |_| Err(DatabaseError::unreplayed_log(path)))
+    }
+
+    /// Sets whether every appended record is synced to disk. Without sync record survives crash
+    /// of the process, but not crash of the system. Records are synced by default.
+    pub fn set_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    pub fn append<H, K, V>(&mut self, record: &LogRecord<H, K, V>) -> Result<(), DatabaseError>
+    where
+        H: Serialize,
+        K: Serialize,
+        V: Serialize,
+    {
+        let payload = bincode::serialize(record).map_err(DatabaseError::serialize_error)?;
+        let length = (payload.len() as u32).to_le_bytes();
+        let checksum = record_checksum(&length, &payload).to_le_bytes();
+
+        self.writer
+            .write_all(&length)
+            .and_then(|_| self.writer.write_all(&checksum))
+            .and_then(|_| self.writer.write_all(&payload))
+            .and_then(|_| self.writer.flush())
+            .map_err(DatabaseError::encoding_error)?;
+
+        if self.sync {
+            self.writer
+                .get_ref()
+                .sync_data()
+                .map_err(DatabaseError::encoding_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes all records, must be called after snapshot containing them was saved.
+    pub fn truncate(&mut self) -> Result<(), DatabaseError> {
+        self.writer.flush().map_err(DatabaseError::encoding_error)?;
+        self.writer
+            .get_ref()
+            .set_len(0)
+            .map_err(DatabaseError::encoding_error)?;
+        // Log left next to the new snapshot after system crash would be replayed twice.
+        self.writer
+            .get_ref()
+            .sync_all()
+            .map_err(DatabaseError::encoding_error)
+    }
+}
+
+/// Reads encoded record from `reader` which has `remaining` bytes left. Returns `None` if log
+/// ends with incomplete record or record does not match its checksum.
+fn read_payload<R>(reader: &mut R, remaining: u64) -> Result<Option<Vec<u8>>, DatabaseError>
+where
+    R: Read,
+{
+    if remaining < RECORD_HEADER_SIZE as u64 {
+        return Ok(None);
+    }
+
+    let mut header = [0; RECORD_HEADER_SIZE];
+
+    reader
+        .read_exact(&mut header)
+        .map_err(DatabaseError::open_file_error)?;
+
+    let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+    if u64::from(length) > remaining - RECORD_HEADER_SIZE as u64 {
+        return Ok(None);
+    }
+
+    let mut payload = vec![0; length as usize];
+
+    reader
+        .read_exact(&mut payload)
+        .map_err(DatabaseError::open_file_error)?;
+
+    if record_checksum(&header[0..4], &payload) != checksum {
+        return Ok(None);
+    }
+
+    Ok(Some(payload))
+}
+
+/// Checksum covers length too, so zero-filled tail does not pass as empty record.
+fn record_checksum(length: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = Crc32Hasher::new();
+
+    hasher.update(length);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wal::LogRecord;
+    use crate::wal::WriteAheadLog;
+    use crate::wal::RECORD_HEADER_SIZE;
+    use crate::DatabaseError;
+    use std::env;
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn log_path(name: &str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("htdb-wal-{}-{}", name, process::id()))
+            .join("wal.htdb");
+
+        fs::remove_file(&path).ok();
+
+        path
+    }
+
+    fn read_records(path: &Path) -> Vec<LogRecord<u32, u32, u32>> {
+        let mut records = Vec::new();
+
+        WriteAheadLog::open(path, |record| {
+            records.push(record);
+
+            Ok(())
+        })
+        .unwrap();
+
+        records
+    }
+
+    #[test]
+    fn open_must_replay_records() {
+        let path = log_path("replay");
+        let mut log = WriteAheadLog::open::<u32, u32, u32, _>(&path, |_| Ok(())).unwrap();
+
+        log.append(&LogRecord::Put {
+            hash_key: &1,
+            tree_key: &2,
+            data: &3,
+        })
+        .unwrap();
+        log.append(&LogRecord::<_, _, &u32>::Delete {
+            hash_key: &1,
+            tree_key: &2,
+        })
+        .unwrap();
+
+        drop(log);
+
+        let records = read_records(&path);
+
+        assert_eq!(2, records.len());
+        assert!(matches!(
+            records[0],
+            LogRecord::Put {
+                hash_key: 1,
+                tree_key: 2,
+                data: 3
+            }
+        ));
+        assert!(matches!(
+            records[1],
+            LogRecord::Delete {
+                hash_key: 1,
+                tree_key: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn open_must_discard_incomplete_record() {
+        let path = log_path("incomplete");
+        let mut log = WriteAheadLog::open::<u32, u32, u32, _>(&path, |_| Ok(())).unwrap();
+
+        log.append(&LogRecord::Put {
+            hash_key: &1,
+            tree_key: &2,
+            data: &3,
+        })
+        .unwrap();
+        log.append(&LogRecord::Put {
+            hash_key: &4,
+            tree_key: &5,
+            data: &6,
+        })
+        .unwrap();
+
+        drop(log);
+
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        let length = file.metadata().unwrap().len();
+
+        file.set_len(length - 2).unwrap();
+
+        assert_eq!(1, read_records(&path).len());
+        assert_eq!(length / 2, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn open_must_discard_zero_filled_tail() {
+        let path = log_path("zero-tail");
+        let mut log = WriteAheadLog::open::<u32, u32, u32, _>(&path, |_| Ok(())).unwrap();
+
+        log.append(&LogRecord::Put {
+            hash_key: &1,
+            tree_key: &2,
+            data: &3,
+        })
+        .unwrap();
+
+        drop(log);
+
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        let length = file.metadata().unwrap().len();
+
+        file.set_len(length + 64).unwrap();
+
+        assert_eq!(1, read_records(&path).len());
+        assert_eq!(length, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn open_must_discard_damaged_record() {
+        let path = log_path("damaged");
+        let mut log = WriteAheadLog::open::<u32, u32, u32, _>(&path, |_| Ok(())).unwrap();
+
+        for index in 0..3 {
+            log.append(&LogRecord::Put {
+                hash_key: &index,
+                tree_key: &index,
+                data: &index,
+            })
+            .unwrap();
+        }
+
+        drop(log);
+
+        let mut bytes = fs::read(&path).unwrap();
+        let record_length = bytes.len() / 3;
+
+        bytes[record_length + RECORD_HEADER_SIZE] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(1, read_records(&path).len());
+        assert_eq!(record_length as u64, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn create_must_reject_log_with_records() {
+        let path = log_path("create");
+        let mut log = WriteAheadLog::create::<u32, u32, u32>(&path)
+            .unwrap()
+            .set_sync(false);
+
+        log.append(&LogRecord::<_, _, &u32>::Delete {
+            hash_key: &1,
+            tree_key: &2,
+        })
+        .unwrap();
+
+        drop(log);
+
+        assert!(matches!(
+            WriteAheadLog::create::<u32, u32, u32>(&path),
+            Err(DatabaseError::UnreplayedLog { .. })
+        ));
+        assert_eq!(1, read_records(&path).len());
+    }
+
+    #[test]
+    fn truncate_must_remove_records() {
+        let path = log_path("truncate");
+        let mut log = WriteAheadLog::open::<u32, u32, u32, _>(&path, |_| Ok(())).unwrap();
+
+        log.append(&LogRecord::Put {
+            hash_key: &1,
+            tree_key: &2,
+            data: &3,
+        })
+        .unwrap();
+        log.truncate().unwrap();
+        log.append(&LogRecord::Put {
+            hash_key: &4,
+            tree_key: &5,
+            data: &6,
+        })
+        .unwrap();
+
+        drop(log);
+
+        let records = read_records(&path);
+
+        assert_eq!(1, records.len());
+        assert!(matches!(records[0], LogRecord::Put { hash_key: 4, .. }));
+    }
+}